@@ -1,63 +1,4993 @@
-#[cfg(test)]
-mod test {
-    use super::*;
-    use soroban_sdk::{testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation}, Env, Address};
-
-    #[test]
-    fn test_initialize() {
-        let env = Env::default();
-        env.mock_all_auths();
-        
-        let contract_id = env.register_contract(None, DustAggregator);
-        let client = DustAggregatorClient::new(&env, &contract_id);
-        
-        let admin = Address::generate(&env);
-        
-        client.initialize(&admin, &100); // 1% fee
-        
-        assert_eq!(client.get_admin(), admin);
-        assert_eq!(client.get_fee_rate(), 100);
-        assert!(!client.is_paused());
-    }
-
-    #[test]
-    fn test_deposit_and_withdraw() {
-        let env = Env::default();
-        env.mock_all_auths();
-        
-        let contract_id = env.register_contract(None, DustAggregator);
-        let client = DustAggregatorClient::new(&env, &contract_id);
-        
-        let admin = Address::generate(&env);
-        let user = Address::generate(&env);
-        let token = Address::generate(&env);
-        
-        client.initialize(&admin, &0);
-        
-        // Mock token contract would be needed for full test
-        // This is a simplified test structure
-        
-        // Test would verify deposit and withdrawal functionality
-        // In practice, you'd need to deploy a test token contract
-    }
-
-    #[test]
-    fn test_pause_functionality() {
-        let env = Env::default();
-        env.mock_all_auths();
-        
-        let contract_id = env.register_contract(None, DustAggregator);
-        let client = DustAggregatorClient::new(&env, &contract_id);
-        
-        let admin = Address::generate(&env);
-        
-        client.initialize(&admin, &0);
-        assert!(!client.is_paused());
-        
-        client.set_paused(&true);
-        assert!(client.is_paused());
-        
-        client.set_paused(&false);
-        assert!(!client.is_paused());
+#![cfg(test)]
+extern crate std;
+
+use super::*;
+use soroban_sdk::{
+    testutils::{Address as _, Events, Ledger},
+    token::StellarAssetClient,
+    Env, Address, TryFromVal,
+};
+
+// Mock storage keys
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MockKey {
+    Status,
+    Position,
+    Submitted,
+    Pools,
+    Rate,
+    Auction(u32, Address),
+    AllowanceSubmitted,
+    Price(Address),
+    LastUpdated,
+    Reserve(Address),
+    RejectWithdrawals,
+    PathOutput(u32),
+    Reserves,
+    Rewards(Address),
+    PriceCalls,
+    MoveTokens,
+}
+
+// Mock Blend pool that records submitted requests and serves a configurable position
+#[contract]
+pub struct MockBlendPool;
+
+#[contractimpl]
+impl MockBlendPool {
+    pub fn set_status(env: Env, status: u32) {
+        env.storage().instance().set(&MockKey::Status, &status);
+    }
+
+    pub fn set_position(env: Env, position: UserPositionData) {
+        env.storage().instance().set(&MockKey::Position, &position);
+    }
+
+    pub fn submitted(env: Env) -> Vec<Request> {
+        env.storage().instance().get(&MockKey::Submitted).unwrap_or(Vec::new(&env))
+    }
+
+    pub fn set_auction(env: Env, auction_type: u32, user: Address, auction: AuctionData) {
+        env.storage().instance().set(&MockKey::Auction(auction_type, user), &auction);
+    }
+
+    pub fn get_auction(env: Env, auction_type: u32, user: Address) -> AuctionData {
+        env.storage().instance().get(&MockKey::Auction(auction_type, user)).expect("no auction")
+    }
+
+    pub fn set_move_tokens(env: Env, move_tokens: bool) {
+        env.storage().instance().set(&MockKey::MoveTokens, &move_tokens);
+    }
+
+    pub fn set_reject_withdrawals(env: Env, reject: bool) {
+        env.storage().instance().set(&MockKey::RejectWithdrawals, &reject);
+    }
+
+    pub fn submit(env: Env, from: Address, _spender: Address, to: Address, requests: Vec<Request>) {
+        let reject_withdrawals: bool = env.storage().instance().get(&MockKey::RejectWithdrawals).unwrap_or(false);
+        let is_withdrawal = requests.iter().any(|request| {
+            request.request_type == REQUEST_WITHDRAW || request.request_type == REQUEST_WITHDRAW_COLLATERAL
+        });
+        if reject_withdrawals && is_withdrawal {
+            panic!("insufficient liquidity");
+        }
+
+        // Once enabled, deposits and repays are pulled under the caller's allowance and
+        // withdrawals and borrows are paid from the pool's own token balance
+        let move_tokens: bool = env.storage().instance().get(&MockKey::MoveTokens).unwrap_or(false);
+        for request in requests.iter().filter(|_| move_tokens) {
+            let token = TokenClient::new(&env, &request.address);
+            let pool = env.current_contract_address();
+            match request.request_type {
+                REQUEST_DEPOSIT | REQUEST_DEPOSIT_COLLATERAL | REQUEST_REPAY => {
+                    token.transfer_from(&pool, &from, &pool, &request.amount)
+                }
+                REQUEST_WITHDRAW | REQUEST_WITHDRAW_COLLATERAL | REQUEST_BORROW => {
+                    token.transfer(&pool, &to, &request.amount)
+                }
+                _ => {}
+            }
+        }
+
+        // Auction fills pay out the lot from the pool's own token balance
+        for request in requests.iter() {
+            let auction_type = match request.request_type {
+                REQUEST_FILL_BAD_DEBT_AUCTION => AUCTION_TYPE_BAD_DEBT,
+                REQUEST_FILL_INTEREST_AUCTION => AUCTION_TYPE_INTEREST,
+                _ => continue,
+            };
+            let auction = Self::get_auction(env.clone(), auction_type, request.address.clone());
+            for (token, amount) in auction.lot.iter() {
+                TokenClient::new(&env, &token).transfer(&env.current_contract_address(), &to, &amount);
+            }
+        }
+        Self::record(&env, requests);
+    }
+
+    pub fn allowance_submitted(env: Env) -> Vec<Request> {
+        env.storage().instance().get(&MockKey::AllowanceSubmitted).unwrap_or(Vec::new(&env))
+    }
+
+    pub fn submit_with_allowance(env: Env, _from: Address, _spender: Address, _to: Address, requests: Vec<Request>) {
+        let mut allowance_submitted = Self::allowance_submitted(env.clone());
+        allowance_submitted.append(&requests);
+        env.storage().instance().set(&MockKey::AllowanceSubmitted, &allowance_submitted);
+        Self::record(&env, requests);
+    }
+
+    pub fn flash_loan(env: Env, _from: Address, _spender: Address, _to: Address, requests: Vec<Request>) {
+        Self::record(&env, requests);
+    }
+
+    pub fn get_user_position(env: Env, _user: Address) -> UserPositionData {
+        env.storage().instance().get(&MockKey::Position).unwrap_or(UserPositionData {
+            collateral: Map::new(&env),
+            liabilities: Map::new(&env),
+            supply: Map::new(&env),
+        })
+    }
+
+    pub fn get_pool_status(env: Env) -> u32 {
+        env.storage().instance().get(&MockKey::Status).unwrap_or(0)
+    }
+
+    pub fn set_reserve(env: Env, asset: Address, reserve: PoolReserve) {
+        env.storage().instance().set(&MockKey::Reserve(asset), &reserve);
+    }
+
+    pub fn get_reserve(env: Env, asset: Address) -> PoolReserve {
+        env.storage().instance().get(&MockKey::Reserve(asset)).unwrap_or(PoolReserve {
+            total_supply: 0,
+            total_liabilities: 0,
+        })
+    }
+
+    pub fn set_rewards(env: Env, reward_token: Address, amount: i128) {
+        env.storage().instance().set(&MockKey::Rewards(reward_token), &amount);
+    }
+
+    pub fn claim_rewards(env: Env, _from: Address, reward_token: Address) -> i128 {
+        let amount: i128 = env.storage().instance().get(&MockKey::Rewards(reward_token.clone())).unwrap_or(0);
+        env.storage().instance().set(&MockKey::Rewards(reward_token), &0i128);
+        amount
+    }
+
+    pub fn set_reserves(env: Env, reserves: PoolReserve) {
+        env.storage().instance().set(&MockKey::Reserves, &reserves);
+    }
+
+    pub fn get_reserves(env: Env) -> PoolReserve {
+        env.storage().instance().get(&MockKey::Reserves).unwrap_or(PoolReserve {
+            total_supply: 0,
+            total_liabilities: 0,
+        })
+    }
+
+    fn record(env: &Env, requests: Vec<Request>) {
+        let mut submitted = Self::submitted(env.clone());
+        submitted.append(&requests);
+        env.storage().instance().set(&MockKey::Submitted, &submitted);
+    }
+}
+
+// Mock Blend pool factory registered at the hardcoded factory address
+#[contract]
+pub struct MockPoolFactory;
+
+#[contractimpl]
+impl MockPoolFactory {
+    pub fn set_pool(env: Env, pool: Address, valid: bool) {
+        let mut pools: Map<Address, bool> = env.storage().instance().get(&MockKey::Pools).unwrap_or(Map::new(&env));
+        pools.set(pool, valid);
+        env.storage().instance().set(&MockKey::Pools, &pools);
+    }
+
+    pub fn is_pool(env: Env, pool: Address) -> bool {
+        let pools: Map<Address, bool> = env.storage().instance().get(&MockKey::Pools).unwrap_or(Map::new(&env));
+        pools.get(pool).unwrap_or(false)
+    }
+}
+
+// Mock Blend oracle registered at the hardcoded oracle address
+#[contract]
+pub struct MockOracle;
+
+#[contractimpl]
+impl MockOracle {
+    pub fn set_price(env: Env, asset: Address, price: i128) {
+        env.storage().instance().set(&MockKey::Price(asset), &price);
+    }
+
+    pub fn get_price(env: Env, asset: Address) -> i128 {
+        let calls: u32 = env.storage().instance().get(&MockKey::PriceCalls).unwrap_or(0);
+        env.storage().instance().set(&MockKey::PriceCalls, &(calls + 1));
+        env.storage().instance().get(&MockKey::Price(asset)).expect("no price")
+    }
+
+    pub fn price_calls(env: Env) -> u32 {
+        env.storage().instance().get(&MockKey::PriceCalls).unwrap_or(0)
+    }
+
+    pub fn set_last_updated(env: Env, timestamp: u64) {
+        env.storage().instance().set(&MockKey::LastUpdated, &timestamp);
+    }
+
+    pub fn last_updated(env: Env, _asset: Address) -> u64 {
+        env.storage().instance().get(&MockKey::LastUpdated).unwrap_or(env.ledger().timestamp())
+    }
+}
+
+// Mock DEX router applying a fixed rate (in bps) on every hop
+#[contract]
+pub struct MockRouter;
+
+#[contractimpl]
+impl MockRouter {
+    pub fn set_rate(env: Env, rate_bps: i128) {
+        env.storage().instance().set(&MockKey::Rate, &rate_bps);
+    }
+
+    pub fn set_path_output(env: Env, hops: u32, amount_out: i128) {
+        env.storage().instance().set(&MockKey::PathOutput(hops), &amount_out);
+    }
+
+    pub fn get_amounts_out(env: Env, amount_in: i128, path: Vec<Address>) -> Vec<i128> {
+        let rate: i128 = env.storage().instance().get(&MockKey::Rate).unwrap_or(10000);
+        let mut amounts = Vec::from_array(&env, [amount_in]);
+        let mut amount = amount_in;
+        for _ in 1..path.len() {
+            amount = amount * rate / 10000;
+            amounts.push_back(amount);
+        }
+        if let Some(amount_out) = env.storage().instance().get(&MockKey::PathOutput(path.len() - 1)) {
+            amounts.set(amounts.len() - 1, amount_out);
+        }
+        amounts
+    }
+
+    pub fn swap_exact_tokens_for_tokens(
+        env: Env,
+        amount_in: i128,
+        amount_out_min: i128,
+        path: Vec<Address>,
+        to: Address,
+        _deadline: u64,
+    ) -> Vec<i128> {
+        let amounts = Self::get_amounts_out(env.clone(), amount_in, path.clone());
+        let amount_out = amounts.last().unwrap();
+        if amount_out < amount_out_min {
+            panic!("insufficient output amount");
+        }
+
+        // Pull the input under the caller's allowance and pay the output from the router's own balance
+        let router = env.current_contract_address();
+        TokenClient::new(&env, &path.first().unwrap()).transfer_from(&router, &to, &router, &amount_in);
+        TokenClient::new(&env, &path.last().unwrap()).transfer(&router, &to, &amount_out);
+        amounts
+    }
+}
+
+struct TestSetup<'a> {
+    env: Env,
+    client: DustAggregatorClient<'a>,
+    contract_id: Address,
+    admin: Address,
+    pool: MockBlendPoolClient<'a>,
+    factory: MockPoolFactoryClient<'a>,
+}
+
+fn setup<'a>() -> TestSetup<'a> {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    // Keep entries live across long ledger jumps (e.g. fee time locks)
+    env.ledger().with_mut(|li| {
+        li.min_persistent_entry_ttl = 1_000_000;
+        li.min_temp_entry_ttl = 1_000_000;
+        li.max_entry_ttl = 2_000_000;
+    });
+
+    let factory_address = Address::from_string(&String::from_str(&env, BLEND_POOL_FACTORY));
+    env.register_at(&factory_address, MockPoolFactory, ());
+    let factory = MockPoolFactoryClient::new(&env, &factory_address);
+
+    let pool_id = env.register(MockBlendPool, ());
+    let pool = MockBlendPoolClient::new(&env, &pool_id);
+    factory.set_pool(&pool_id, &true);
+
+    let contract_id = env.register(DustAggregator, ());
+    let client = DustAggregatorClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin, &100, &pool_id, &HEALTH_FACTOR_SCALE);
+
+    // Simulated arbitrage profits are never minted, so fees accumulate unless a test opts in;
+    // flash loans are open to any caller unless a test turns the whitelist back on
+    env.as_contract(&contract_id, || {
+        let mut config: ContractConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.defer_fee_transfer = true;
+        config.arbitrage_whitelist_enabled = false;
+        env.storage().instance().set(&DataKey::Config, &config);
+    });
+
+    TestSetup { env, client, contract_id, admin, pool, factory }
+}
+
+fn create_token(env: &Env) -> Address {
+    let token_admin = Address::generate(env);
+    env.register_stellar_asset_contract_v2(token_admin).address()
+}
+
+fn mint(env: &Env, token: &Address, to: &Address, amount: i128) {
+    StellarAssetClient::new(env, token).mint(to, &amount);
+}
+
+fn position(env: &Env, collateral: &[(Address, i128)], liabilities: &[(Address, i128)]) -> UserPositionData {
+    let mut collateral_map = Map::new(env);
+    for (token, amount) in collateral.iter() {
+        collateral_map.set(token.clone(), *amount);
+    }
+    let mut liabilities_map = Map::new(env);
+    for (token, amount) in liabilities.iter() {
+        liabilities_map.set(token.clone(), *amount);
+    }
+    UserPositionData {
+        collateral: collateral_map,
+        liabilities: liabilities_map,
+        supply: Map::new(env),
+    }
+}
+
+#[test]
+fn test_initialize() {
+    let t = setup();
+
+    let config: ContractConfig = t.env.as_contract(&t.contract_id, || {
+        t.env.storage().instance().get(&DataKey::Config).unwrap()
+    });
+    assert_eq!(config.admin, t.admin);
+    assert_eq!(config.fee_rate, 100);
+    assert!(!config.pause_state.paused);
+    assert_eq!(t.client.get_stats(), (0, 0, 0));
+}
+
+#[test]
+#[should_panic(expected = "Invalid blend pool")]
+fn test_initialize_rejects_unknown_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory_address = Address::from_string(&String::from_str(&env, BLEND_POOL_FACTORY));
+    env.register_at(&factory_address, MockPoolFactory, ());
+
+    let contract_id = env.register(DustAggregator, ());
+    let client = DustAggregatorClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    let pool = Address::generate(&env);
+    client.initialize(&admin, &100, &pool, &HEALTH_FACTOR_SCALE);
+}
+
+#[test]
+fn test_deposit_and_withdraw() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1000, &None);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 1000);
+
+    t.client.withdraw_from_blend(&user, &token, &400);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 600);
+
+    let submitted = t.pool.submitted();
+    assert_eq!(submitted.len(), 2);
+    assert_eq!(submitted.get(0).unwrap().request_type, REQUEST_DEPOSIT_COLLATERAL);
+    assert_eq!(submitted.get(1).unwrap().request_type, REQUEST_WITHDRAW_COLLATERAL);
+}
+
+#[test]
+#[should_panic(expected = "Contract is paused")]
+fn test_pause_functionality() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.set_paused(&t.admin, &true);
+    t.client.set_paused(&t.admin, &false);
+    t.client.set_paused(&t.admin, &true);
+
+    let params = ArbitrageParams {
+        loan_token: token,
+        loan_amount: 1000,
+        swap_path: Vec::new(&t.env),
+        min_profit: 10,
+        slippage_bps: 0,
+        deadline: None,
+        nonce: unique_nonce(&t.env),
+    };
+    t.client.flash_loan_arbitrage(&user, &params);
+}
+
+#[test]
+fn test_fill_liquidation_for_user() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let liquidator = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    // 900 of debt against 1000 of collateral is past the liquidation threshold
+    t.client.supply_to_blend(&user, &collateral, &1000, &None);
+    t.client.borrow_against_dust(&user, &debt, &900, &0);
+
+    mint(&t.env, &debt, &liquidator, 500);
+    mint(&t.env, &collateral, &t.pool.address, 1000);
+    t.pool.set_move_tokens(&true);
+
+    let seized = t.client.fill_liquidation_for_user(&liquidator, &user, &collateral, &debt, &500);
+    assert_eq!(seized, 525);
+
+    let debt_balance = t.client.get_user_balance(&user, &debt);
+    assert_eq!(debt_balance.borrowed_from_blend, 400);
+    let collateral_balance = t.client.get_user_balance(&user, &collateral);
+    assert_eq!(collateral_balance.supplied_to_blend, 475);
+
+    // The seized collateral is withdrawn from the pool, not paid from the contract's balance
+    let debt_token = TokenClient::new(&t.env, &debt);
+    let collateral_token = TokenClient::new(&t.env, &collateral);
+    assert_eq!(debt_token.balance(&liquidator), 0);
+    assert_eq!(debt_token.balance(&t.pool.address), 500);
+    assert_eq!(collateral_token.balance(&liquidator), 525);
+    assert_eq!(collateral_token.balance(&t.pool.address), 475);
+    assert_eq!(collateral_token.balance(&t.contract_id), 0);
+
+    // Both legs act on the contract's own pooled position rather than on the user
+    let submitted = t.pool.submitted();
+    let repay = submitted.get(submitted.len() - 2).unwrap();
+    assert_eq!((repay.request_type, repay.address, repay.amount), (REQUEST_REPAY, debt.clone(), 500));
+    let withdraw = submitted.last().unwrap();
+    assert_eq!((withdraw.request_type, withdraw.address, withdraw.amount), (REQUEST_WITHDRAW_COLLATERAL, collateral.clone(), 525));
+}
+
+#[test]
+fn test_fill_liquidation_caps_at_outstanding_debt() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let liquidator = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &collateral, &1000, &None);
+    t.client.borrow_against_dust(&user, &debt, &900, &0);
+
+    mint(&t.env, &debt, &liquidator, 1000);
+    mint(&t.env, &collateral, &t.pool.address, 1000);
+    t.pool.set_move_tokens(&true);
+
+    let seized = t.client.fill_liquidation_for_user(&liquidator, &user, &collateral, &debt, &1000);
+    assert_eq!(seized, 945);
+    assert_eq!(t.client.get_user_balance(&user, &debt).borrowed_from_blend, 0);
+    assert_eq!(TokenClient::new(&t.env, &debt).balance(&liquidator), 100);
+}
+
+#[test]
+#[should_panic(expected = "Position not liquidatable")]
+fn test_fill_liquidation_rejects_healthy_position() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let liquidator = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &collateral, &1000, &None);
+    t.client.borrow_against_dust(&user, &debt, &500, &0);
+
+    mint(&t.env, &debt, &liquidator, 500);
+    t.client.fill_liquidation_for_user(&liquidator, &user, &collateral, &debt, &500);
+}
+
+// Deterministic, distinct on every call within an env
+fn unique_nonce(env: &Env) -> BytesN<32> {
+    env.crypto().sha256(&Address::generate(env).to_xdr(env)).to_bytes()
+}
+
+fn arbitrage_params(env: &Env, loan_token: &Address, loan_amount: i128) -> ArbitrageParams {
+    ArbitrageParams {
+        loan_token: loan_token.clone(),
+        loan_amount,
+        swap_path: Vec::new(env),
+        min_profit: loan_amount * MIN_ARBITRAGE_PROFIT_BPS / 10000,
+        slippage_bps: 0,
+        deadline: None,
+        nonce: unique_nonce(env),
+    }
+}
+
+#[test]
+fn test_user_tier_promotion_and_fee_reduction() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let thresholds = TierThresholds {
+        silver_volume: 1_000_000,
+        gold_volume: 2_000_000,
+        platinum_volume: 4_000_000,
+    };
+    let mut multipliers = Map::new(&t.env);
+    multipliers.set(UserTier::Standard as u32, 10000);
+    multipliers.set(UserTier::Silver as u32, 9000);
+    multipliers.set(UserTier::Gold as u32, 7500);
+    multipliers.set(UserTier::Platinum as u32, 5000);
+    t.client.admin_set_tier_config(&t.admin, &thresholds, &multipliers);
+
+    assert_eq!(t.client.get_user_tier(&user), UserTier::Standard);
+    assert_eq!(t.client.get_tier_fee_rate(&user), 100);
+
+    // 15_000 profit per trade; fee depends on the tier held before the trade
+    assert_eq!(t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000)), 14_850);
+    assert_eq!(t.client.get_user_tier(&user), UserTier::Silver);
+    assert_eq!(t.client.get_tier_fee_rate(&user), 90);
+
+    assert_eq!(t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000)), 14_865);
+    assert_eq!(t.client.get_user_tier(&user), UserTier::Gold);
+    assert_eq!(t.client.get_tier_fee_rate(&user), 75);
+
+    assert_eq!(t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000)), 14_888);
+    assert_eq!(t.client.get_user_tier(&user), UserTier::Gold);
+
+    assert_eq!(t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000)), 14_888);
+    assert_eq!(t.client.get_user_tier(&user), UserTier::Platinum);
+    assert_eq!(t.client.get_tier_fee_rate(&user), 50);
+
+    assert_eq!(t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000)), 14_925);
+}
+
+#[test]
+fn test_user_tier_below_boundary_stays_standard() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 99_999));
+    assert_eq!(t.client.get_user_tier(&user), UserTier::Standard);
+
+    t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1));
+    assert_eq!(t.client.get_user_tier(&user), UserTier::Silver);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_set_tier_config_requires_admin() {
+    let t = setup();
+    let not_admin = Address::generate(&t.env);
+    let thresholds = TierThresholds {
+        silver_volume: 1,
+        gold_volume: 2,
+        platinum_volume: 3,
+    };
+    t.client.admin_set_tier_config(&not_admin, &thresholds, &Map::new(&t.env));
+}
+
+#[test]
+fn test_auto_yield_target_splits_supply() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let mut targets = Map::new(&t.env);
+    targets.set(token.clone(), 5000u32);
+    t.client.set_auto_yield_targets(&user, &targets);
+
+    t.client.supply_to_blend(&user, &token, &1000, &None);
+
+    let balance = t.client.get_user_balance(&user, &token);
+    assert_eq!(balance.supplied_to_blend, 500);
+    assert_eq!(balance.balance, 500);
+    assert_eq!(t.pool.submitted().get(0).unwrap().amount, 500);
+}
+
+#[test]
+fn test_auto_yield_zero_target_skips_blend() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let mut targets = Map::new(&t.env);
+    targets.set(token.clone(), 0u32);
+    t.client.set_auto_yield_targets(&user, &targets);
+
+    t.client.supply_to_blend(&user, &token, &1000, &None);
+
+    let balance = t.client.get_user_balance(&user, &token);
+    assert_eq!(balance.supplied_to_blend, 0);
+    assert_eq!(balance.balance, 1000);
+    assert_eq!(t.pool.submitted().len(), 0);
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_auto_yield_targets_over_100_percent_rejected() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+
+    let mut targets = Map::new(&t.env);
+    targets.set(create_token(&t.env), 6000u32);
+    targets.set(create_token(&t.env), 5000u32);
+    t.client.set_auto_yield_targets(&user, &targets);
+}
+
+fn token_config(deposit_cap: i128) -> TokenConfig {
+    TokenConfig {
+        deposit_cap,
+        use_allowance_submit: false,
+        oracle_bounds: OracleBounds {
+            min_price: 0,
+            max_price: 0,
+        },
+        reserve_factor_bps: 0,
+        collateral_factor_bps: 10000,
+        borrow_cap: 0,
+        max_ltv_bps: 0,
+    }
+}
+
+fn user_balance(token: &Address, balance: i128, supplied: i128, borrowed: i128) -> UserBalance {
+    UserBalance {
+        token: token.clone(),
+        balance,
+        supplied_to_blend: supplied,
+        deposited_to_blend: 0,
+        borrowed_from_blend: borrowed,
+        last_updated: 0,
+        accrued_interest: 0,
+        realized_pnl: 0,
+        is_imported: false,
+        active_pool: Address::generate(token.env()),
+        last_interaction_type: Symbol::new(token.env(), ""),
+        interaction_count: 0,
+        first_supply_ledger: NEVER_SUPPLIED,
+    }
+}
+
+#[test]
+fn test_portfolio_summary_aggregates_tokens() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+
+    // XLM is priced at $0.12, USDC at $1.00
+    let xlm = Address::from_string(&String::from_str(&t.env, XLM_TOKEN));
+    let usdc = Address::from_string(&String::from_str(&t.env, USDC_TOKEN));
+
+    t.env.as_contract(&t.contract_id, || {
+        let mut balances = Map::new(&t.env);
+        let mut xlm_balance = user_balance(&xlm, 500, 10_000, 0);
+        xlm_balance.active_pool = t.pool.address.clone();
+        balances.set(xlm.clone(), xlm_balance);
+        let mut usdc_balance = user_balance(&usdc, 100, 1_800, 400);
+        usdc_balance.active_pool = t.pool.address.clone();
+        balances.set(usdc.clone(), usdc_balance);
+        t.env.storage().persistent().set(&DataKey::UserBalances(user.clone()), &balances);
+    });
+    set_supply_rate(&t, &xlm, 300);
+    set_supply_rate(&t, &usdc, 800);
+
+    t.pool.set_position(&position(&t.env, &[(usdc.clone(), 3_000)], &[(usdc.clone(), 400)]));
+
+    let summary = t.client.get_portfolio_summary(&user);
+
+    let xlm_supplied_usd = 10_000 * 120_000 / 1_000_000;
+    let usdc_supplied_usd = 1_800;
+    assert_eq!(summary.tokens.len(), 2);
+    assert_eq!(summary.total_supplied_usd, xlm_supplied_usd + usdc_supplied_usd);
+    assert_eq!(summary.total_borrowed_usd, 400);
+    assert_eq!(summary.total_balance_usd, 500 * 120_000 / 1_000_000 + 100);
+    assert_eq!(
+        summary.net_apy_bps,
+        (xlm_supplied_usd * 300 + usdc_supplied_usd * 800) / (xlm_supplied_usd + usdc_supplied_usd)
+    );
+    assert_eq!(summary.health_factor, 3_000 * 8000 * HEALTH_FACTOR_SCALE / 400 / 10000);
+}
+
+#[test]
+fn test_portfolio_summary_empty() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+
+    let summary = t.client.get_portfolio_summary(&user);
+    assert_eq!(summary.tokens.len(), 0);
+    assert_eq!(summary.total_supplied_usd, 0);
+    assert_eq!(summary.net_apy_bps, 0);
+    assert_eq!(summary.health_factor, i128::MAX);
+}
+
+fn read_config(t: &TestSetup) -> ContractConfig {
+    t.env.as_contract(&t.contract_id, || {
+        t.env.storage().instance().get(&DataKey::Config).unwrap()
+    })
+}
+
+#[test]
+fn test_emergency_admin_can_pause_and_unpause() {
+    let t = setup();
+    let emergency_admin = Address::generate(&t.env);
+    t.client.set_emergency_admin(&t.admin, &emergency_admin);
+
+    t.client.emergency_pause(&emergency_admin);
+    let config = read_config(&t);
+    assert!(config.pause_state.paused);
+    assert!(config.emergency_mode);
+
+    t.client.emergency_unpause(&emergency_admin);
+    let config = read_config(&t);
+    assert!(!config.pause_state.paused);
+    assert!(!config.emergency_mode);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_emergency_admin_cannot_withdraw_fees() {
+    let t = setup();
+    let emergency_admin = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    t.client.set_emergency_admin(&t.admin, &emergency_admin);
+
+    t.client.admin_withdraw_fees(&emergency_admin, &token, &1);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_emergency_pause_rejects_other_callers() {
+    let t = setup();
+    let emergency_admin = Address::generate(&t.env);
+    t.client.set_emergency_admin(&t.admin, &emergency_admin);
+
+    t.client.emergency_pause(&Address::generate(&t.env));
+}
+
+#[test]
+fn test_admin_withdraw_fees() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let recipient = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.admin_set_fee_recipient(&t.admin, &recipient);
+    assert_eq!(t.client.get_fee_recipient(), recipient);
+
+    // 15_000 profit at 1% fee
+    t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000));
+    assert_eq!(t.client.get_collected_fees(&token), 150);
+
+    mint(&t.env, &token, &t.contract_id, 150);
+    t.client.admin_withdraw_fees(&t.admin, &token, &100);
+
+    assert_eq!(t.client.get_collected_fees(&token), 50);
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&recipient), 100);
+}
+
+fn read_blend_config(t: &TestSetup) -> BlendConfig {
+    t.env.as_contract(&t.contract_id, || {
+        t.env.storage().instance().get(&DataKey::BlendConfig).unwrap()
+    })
+}
+
+#[test]
+fn test_pool_rotation_with_lazy_migration() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1000, &None);
+
+    let new_pool_id = t.env.register(MockBlendPool, ());
+    let new_pool = MockBlendPoolClient::new(&t.env, &new_pool_id);
+    t.factory.set_pool(&new_pool_id, &true);
+
+    let deadline = t.env.ledger().sequence() + 100;
+    t.client.admin_initiate_pool_rotation(&t.admin, &new_pool_id, &deadline);
+    t.client.admin_confirm_pool_rotation(&t.admin);
+    assert_eq!(read_blend_config(&t).pool_address, new_pool_id);
+
+    // Next supply moves the old position over before depositing
+    t.client.supply_to_blend(&user, &token, &200, &None);
+
+    let old_requests = t.pool.submitted();
+    let withdraw = old_requests.last().unwrap();
+    assert_eq!(withdraw.request_type, REQUEST_WITHDRAW_COLLATERAL);
+    assert_eq!(withdraw.amount, 1000);
+
+    let new_requests = new_pool.submitted();
+    assert_eq!(new_requests.len(), 2);
+    assert_eq!(new_requests.get(0).unwrap().request_type, REQUEST_DEPOSIT_COLLATERAL);
+    assert_eq!(new_requests.get(0).unwrap().amount, 1000);
+    assert_eq!(new_requests.get(1).unwrap().amount, 200);
+
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 1200);
+
+    // Migration only happens once
+    t.client.supply_to_blend(&user, &token, &100, &None);
+    assert_eq!(t.pool.submitted().len(), old_requests.len());
+}
+
+#[test]
+#[should_panic(expected = "Pool rotation deadline passed")]
+fn test_pool_rotation_deadline_passed() {
+    let t = setup();
+    let new_pool_id = t.env.register(MockBlendPool, ());
+    t.factory.set_pool(&new_pool_id, &true);
+
+    let deadline = t.env.ledger().sequence() + 10;
+    t.client.admin_initiate_pool_rotation(&t.admin, &new_pool_id, &deadline);
+
+    t.env.ledger().with_mut(|li| li.sequence_number = deadline + 1);
+    t.client.admin_confirm_pool_rotation(&t.admin);
+}
+
+#[test]
+#[should_panic(expected = "Invalid blend pool")]
+fn test_pool_rotation_rejects_unknown_pool() {
+    let t = setup();
+    let new_pool_id = t.env.register(MockBlendPool, ());
+
+    let deadline = t.env.ledger().sequence() + 10;
+    t.client.admin_initiate_pool_rotation(&t.admin, &new_pool_id, &deadline);
+    t.client.admin_confirm_pool_rotation(&t.admin);
+}
+
+#[test]
+fn test_batch_repay_full_and_partial() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token_a = create_token(&t.env);
+    let token_b = create_token(&t.env);
+
+    t.client.borrow_against_dust(&user, &token_a, &500, &0);
+    t.client.borrow_against_dust(&user, &token_b, &300, &0);
+    let submitted_before = t.pool.submitted().len();
+
+    let tokens = Vec::from_array(&t.env, [token_a.clone(), token_b.clone()]);
+    let amounts = Vec::from_array(&t.env, [500i128, 100i128]);
+    t.client.batch_repay(&user, &tokens, &amounts);
+
+    assert_eq!(t.client.get_user_balance(&user, &token_a).borrowed_from_blend, 0);
+    assert_eq!(t.client.get_user_balance(&user, &token_b).borrowed_from_blend, 200);
+
+    // A single submission carries both repayments
+    let submitted = t.pool.submitted();
+    assert_eq!(submitted.len(), submitted_before + 2);
+    assert_eq!(submitted.get(submitted_before).unwrap().request_type, REQUEST_REPAY);
+    assert_eq!(submitted.get(submitted_before + 1).unwrap().request_type, REQUEST_REPAY);
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_batch_repay_rejects_amount_above_debt() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token_a = create_token(&t.env);
+    let token_b = create_token(&t.env);
+
+    t.client.borrow_against_dust(&user, &token_a, &500, &0);
+    t.client.borrow_against_dust(&user, &token_b, &300, &0);
+
+    let tokens = Vec::from_array(&t.env, [token_a, token_b]);
+    let amounts = Vec::from_array(&t.env, [100i128, 301i128]);
+    t.client.batch_repay(&user, &tokens, &amounts);
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_batch_repay_rejects_length_mismatch() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.borrow_against_dust(&user, &token, &500, &0);
+    t.client.batch_repay(&user, &Vec::from_array(&t.env, [token]), &Vec::new(&t.env));
+}
+
+#[test]
+fn test_active_users_count_through_cycles() {
+    let t = setup();
+    let user_a = Address::generate(&t.env);
+    let user_b = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user_a, &token, &1000, &None);
+    t.client.supply_to_blend(&user_a, &token, &500, &None);
+    t.client.supply_to_blend(&user_b, &token, &200, &None);
+    assert_eq!(t.client.get_stats().2, 2);
+    assert_eq!(t.client.get_active_users(&0, &10), Vec::from_array(&t.env, [user_a.clone(), user_b.clone()]));
+
+    // Partial withdrawal keeps the user active
+    t.client.withdraw_from_blend(&user_a, &token, &1000);
+    assert_eq!(t.client.get_stats().2, 2);
+
+    t.client.withdraw_from_blend(&user_a, &token, &500);
+    assert_eq!(t.client.get_stats().2, 1);
+    assert_eq!(t.client.get_active_users(&0, &10), Vec::from_array(&t.env, [user_b.clone()]));
+
+    // Re-deposit brings them back
+    t.client.supply_to_blend(&user_a, &token, &100, &None);
+    assert_eq!(t.client.get_stats().2, 2);
+    assert_eq!(t.client.get_active_users(&1, &10), Vec::from_array(&t.env, [user_a.clone()]));
+    assert_eq!(t.client.get_active_users(&5, &10).len(), 0);
+}
+
+fn setup_router<'a>(t: &TestSetup<'a>, rate_bps: i128) -> MockRouterClient<'a> {
+    let router_id = t.env.register(MockRouter, ());
+    let router = MockRouterClient::new(&t.env, &router_id);
+    router.set_rate(&rate_bps);
+    t.client.admin_set_router(&t.admin, &router_id);
+    router
+}
+
+#[test]
+fn test_simulate_arbitrage_viable() {
+    let t = setup();
+    let token = create_token(&t.env);
+    let other = create_token(&t.env);
+    setup_router(&t, 10100);
+
+    let mut params = arbitrage_params(&t.env, &token, 1_000_000);
+    params.swap_path = Vec::from_array(&t.env, [token.clone(), other, token.clone()]);
+    params.slippage_bps = 50;
+    params.min_profit = 10_000;
+
+    // 1_000_000 -> 1_010_000 -> 1_020_100, minus 0.5% slippage
+    let result = t.client.simulate_arbitrage(&params);
+    assert_eq!(result.estimated_profit, 1_020_100 * 9950 / 10000 - 1_000_000);
+    assert_eq!(result.fee_amount, result.estimated_profit * 100 / 10000);
+    assert_eq!(result.net_profit, result.estimated_profit - result.fee_amount);
+    assert_eq!(result.estimated_gas_cost, ARBITRAGE_BASE_GAS_COST + 2 * ARBITRAGE_GAS_COST_PER_HOP);
+    assert!(result.viable);
+}
+
+#[test]
+fn test_simulate_arbitrage_not_viable_when_profit_too_low() {
+    let t = setup();
+    let token = create_token(&t.env);
+    let other = create_token(&t.env);
+    setup_router(&t, 10010);
+
+    let mut params = arbitrage_params(&t.env, &token, 1_000_000);
+    params.swap_path = Vec::from_array(&t.env, [token.clone(), other, token.clone()]);
+    params.min_profit = 10_000;
+
+    let result = t.client.simulate_arbitrage(&params);
+    assert!(result.net_profit < params.min_profit);
+    assert!(!result.viable);
+}
+
+fn set_supply_rate(t: &TestSetup, token: &Address, apy_bps: i128) {
+    // A flat 20% borrow rate scaled by the reserve's utilization pays `apy_bps` to suppliers
+    t.client.admin_set_dynamic_fee_config(&t.admin, &DynamicFeeConfig {
+        base_rate_bps: 2000,
+        slope1_bps: 0,
+        slope2_bps: 0,
+        optimal_utilization_bps: 8000,
+    });
+    let total_supply = 1_000_000_000_000;
+    t.pool.set_reserve(token, &PoolReserve { total_supply, total_liabilities: total_supply * apy_bps / 2000 });
+}
+
+#[test]
+fn test_withdraw_earned_yield_only_keeps_principal() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    set_supply_rate(&t, &token, 1000);
+    t.client.supply_to_blend(&user, &token, &1_000_000, &None);
+
+    // Half a year at 10% APY
+    t.env.ledger().with_mut(|li| li.timestamp += (SECONDS_PER_YEAR / 2) as u64);
+    mint(&t.env, &token, &t.contract_id, 50_000);
+
+    let before = t.client.get_user_balance(&user, &token);
+    let harvested = t.client.withdraw_earned_yield_only(&user, &token);
+    assert_eq!(harvested, 50_000);
+
+    let after = t.client.get_user_balance(&user, &token);
+    assert_eq!(after.supplied_to_blend, before.supplied_to_blend);
+    assert_eq!(after.accrued_interest, 0);
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&user), 50_000);
+    assert_eq!(t.client.get_stats().1, 50_000);
+
+    let last = t.pool.submitted().last().unwrap();
+    assert_eq!(last.request_type, REQUEST_WITHDRAW_COLLATERAL);
+    assert_eq!(last.amount, 50_000);
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_withdraw_earned_yield_only_without_interest() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1_000_000, &None);
+    t.client.withdraw_earned_yield_only(&user, &token);
+}
+
+#[test]
+fn test_fee_rate_change_is_time_locked() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let params = arbitrage_params(&t.env, &token, 1_000_000);
+
+    t.client.admin_propose_fee_rate(&t.admin, &300);
+    let pending = t.client.get_pending_fee_rate().unwrap();
+    assert_eq!(pending.effective_after_ledger, t.env.ledger().sequence() + FEE_CHANGE_DELAY_LEDGERS);
+
+    // Old 1% rate still applies up to the threshold ledger
+    t.env.ledger().with_mut(|li| li.sequence_number = pending.effective_after_ledger);
+    assert_eq!(t.client.flash_loan_arbitrage(&user, &params), 14_850);
+
+    // Fresh user so the first trade's tier upgrade doesn't discount the fee
+    let new_user = Address::generate(&t.env);
+    t.env.ledger().with_mut(|li| li.sequence_number = pending.effective_after_ledger + 1);
+    assert_eq!(t.client.flash_loan_arbitrage(&new_user, &params), 14_550);
+    assert_eq!(read_config(&t).fee_rate, 300);
+    assert_eq!(t.client.get_pending_fee_rate(), None);
+}
+
+#[test]
+fn test_cancel_fee_proposal() {
+    let t = setup();
+
+    t.client.admin_propose_fee_rate(&t.admin, &300);
+    t.client.admin_cancel_fee_proposal(&t.admin);
+    assert_eq!(t.client.get_pending_fee_rate(), None);
+
+    t.env.ledger().with_mut(|li| li.sequence_number += FEE_CHANGE_DELAY_LEDGERS + 1);
+    assert_eq!(t.client.get_tier_fee_rate(&t.admin), 100);
+}
+
+// DustEvents published by the most recent contract invocation
+fn dust_events(t: &TestSetup) -> std::vec::Vec<DustEvent> {
+    let mut events = std::vec::Vec::new();
+    for (_, _, data) in t.env.events().all().iter() {
+        if let Ok(event) = DustEvent::try_from_val(&t.env, &data) {
+            events.push(event);
+        }
+    }
+    events
+}
+
+fn governance_events(t: &TestSetup) -> std::vec::Vec<GovernanceEvent> {
+    let topic = Symbol::new(&t.env, "GovernanceEvent");
+    let mut events = std::vec::Vec::new();
+    for (_, topics, data) in t.env.events().all().iter() {
+        if topics.get(0).is_some_and(|first| Symbol::try_from_val(&t.env, &first) == Ok(topic.clone())) {
+            events.push(GovernanceEvent::try_from_val(&t.env, &data).unwrap());
+        }
+    }
+    events
+}
+
+fn health_event(t: &TestSetup) -> Option<DustEvent> {
+    dust_events(t).into_iter().find(|event| {
+        matches!(event, DustEvent::PositionHealthWarning(..) | DustEvent::PositionHealthCritical(..))
+    })
+}
+
+#[test]
+fn test_position_health_events_at_boundaries() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    // Health factor 1.6: above warning threshold (1.2)
+    t.pool.set_position(&position(&t.env, &[(collateral.clone(), 1000)], &[(debt.clone(), 500)]));
+    t.client.supply_to_blend(&user, &collateral, &1000, &None);
+    assert_eq!(health_event(&t), None);
+
+    // Exactly at warning threshold is still healthy
+    t.pool.set_position(&position(&t.env, &[(collateral.clone(), 1500)], &[(debt.clone(), 1000)]));
+    t.client.borrow_against_dust(&user, &debt, &500, &0);
+    assert_eq!(health_event(&t), None);
+
+    // Exactly at the minimum is a warning, not critical
+    t.pool.set_position(&position(&t.env, &[(collateral.clone(), 1250)], &[(debt.clone(), 1000)]));
+    t.client.borrow_against_dust(&user, &debt, &100, &0);
+    assert_eq!(health_event(&t), Some(DustEvent::PositionHealthWarning(user.clone(), HEALTH_FACTOR_SCALE)));
+
+    // Below the minimum is critical
+    t.pool.set_position(&position(&t.env, &[(collateral.clone(), 1000)], &[(debt.clone(), 1000)]));
+    t.client.withdraw_from_blend(&user, &collateral, &100);
+    assert_eq!(health_event(&t), Some(DustEvent::PositionHealthCritical(user.clone(), 800_000)));
+
+    t.client.repay_blend_debt(&user, &debt, &100);
+    assert_eq!(health_event(&t), Some(DustEvent::PositionHealthCritical(user.clone(), 800_000)));
+}
+
+#[test]
+fn test_health_thresholds_configurable() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.admin_set_health_thresholds(&t.admin, &(HEALTH_FACTOR_SCALE / 2), &(HEALTH_FACTOR_SCALE * 2));
+    assert_eq!(read_blend_config(&t).warning_health_factor, HEALTH_FACTOR_SCALE * 2);
+
+    // Health factor 1.6 now falls inside the warning band
+    t.pool.set_position(&position(&t.env, &[(collateral.clone(), 1000)], &[(debt.clone(), 500)]));
+    t.client.supply_to_blend(&user, &collateral, &1000, &None);
+    assert_eq!(health_event(&t), Some(DustEvent::PositionHealthWarning(user.clone(), 1_600_000)));
+}
+
+#[test]
+fn test_global_tvl_cap() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.admin_add_token(&t.admin, &token, &token_config(0), &None);
+    t.client.admin_set_tvl_cap(&t.admin, &10_000);
+
+    // Just under the cap succeeds
+    t.client.supply_to_blend(&user, &token, &9_999, &None);
+    let utilization = t.client.get_tvl_utilization();
+    assert_eq!(utilization.current_tvl, 9_999);
+    assert_eq!(utilization.cap, 10_000);
+    assert_eq!(utilization.utilization_bps, 9_999);
+    assert_eq!(t.client.get_stats().0, 9_999);
+
+    // Pushing over the cap is rejected
+    assert!(t.client.try_supply_to_blend(&user, &token, &2, &None).is_err());
+
+    t.client.supply_to_blend(&user, &token, &1, &None);
+    assert_eq!(t.client.get_tvl_utilization().utilization_bps, 10_000);
+
+    // Withdrawals free up capacity
+    t.client.withdraw_from_blend(&user, &token, &500);
+    assert_eq!(t.client.get_tvl_utilization().current_tvl, 9_500);
+    t.client.supply_to_blend(&user, &token, &500, &None);
+}
+
+#[test]
+#[should_panic(expected = "Deposit cap reached")]
+fn test_token_deposit_cap() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.admin_add_token(&t.admin, &token, &token_config(1_000), &None);
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    t.client.supply_to_blend(&user, &token, &1, &None);
+}
+
+#[test]
+fn test_referral_volume_rewards_and_claim() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let referrer = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let referral = Some(referrer.clone());
+
+    // Below threshold: volume accumulates, no reward yet
+    t.client.supply_to_blend(&user, &token, &6_000, &referral);
+    assert_eq!(t.client.get_referral_volume(&referrer), 6_000);
+    assert_eq!(t.client.get_referral_rewards(&referrer, &token), 0);
+
+    // Crossing the threshold rewards all 12_000 referred so far
+    t.client.supply_to_blend(&user, &token, &6_000, &referral);
+    assert_eq!(
+        dust_events(&t).last(),
+        Some(&DustEvent::ReferralReward(referrer.clone(), user.clone(), 12))
+    );
+    assert_eq!(t.client.get_referral_rewards(&referrer, &token), 12);
+
+    // Afterwards only the new volume is rewarded
+    t.client.supply_to_blend(&user, &token, &10_000, &referral);
+    assert_eq!(t.client.get_referral_volume(&referrer), 22_000);
+    assert_eq!(t.client.get_referral_rewards(&referrer, &token), 22);
+
+    mint(&t.env, &token, &t.contract_id, 22);
+    assert_eq!(t.client.claim_referral_rewards(&referrer, &token), 22);
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&referrer), 22);
+    assert_eq!(t.client.get_referral_rewards(&referrer, &token), 0);
+}
+
+#[test]
+fn test_self_referral_ignored() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &20_000, &Some(user.clone()));
+    assert_eq!(t.client.get_referral_volume(&user), 0);
+}
+
+#[test]
+fn test_get_blend_pool_health() {
+    let t = setup();
+
+    let health = t.client.get_blend_pool_health(&t.pool.address);
+    assert_eq!(health.status_code, 0);
+    assert!(health.is_accepting_deposits);
+    assert!(health.is_accepting_borrows);
+
+    t.pool.set_status(&3);
+    let health = t.client.get_blend_pool_health(&t.pool.address);
+    assert_eq!(health.status_description, Symbol::new(&t.env, "on_ice"));
+    assert!(health.is_accepting_deposits);
+    assert!(!health.is_accepting_borrows);
+
+    t.pool.set_status(&5);
+    let health = t.client.get_blend_pool_health(&t.pool.address);
+    assert_eq!(health.status_description, Symbol::new(&t.env, "frozen"));
+    assert!(!health.is_accepting_deposits);
+    assert!(!health.is_accepting_borrows);
+}
+
+#[test]
+fn test_admin_validate_pool_status_emits_changes() {
+    let t = setup();
+
+    // First check only records the baseline
+    t.client.admin_validate_pool_status();
+    assert!(dust_events(&t).is_empty());
+
+    t.client.admin_validate_pool_status();
+    assert!(dust_events(&t).is_empty());
+
+    t.pool.set_status(&2);
+    t.client.admin_validate_pool_status();
+    assert_eq!(dust_events(&t), std::vec![DustEvent::PoolStatusChanged(t.pool.address.clone(), 0, 2)]);
+}
+
+#[test]
+fn test_admin_bulk_snapshot_stores_history() {
+    let t = setup();
+    let user_a = Address::generate(&t.env);
+    let user_b = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user_a, &token, &1000, &None);
+    t.client.supply_to_blend(&user_b, &token, &2000, &None);
+
+    let users = Vec::from_array(&t.env, [user_a.clone(), user_b.clone()]);
+    let snapshots = t.client.admin_bulk_snapshot(&t.admin, &users);
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(snapshots.get(1).unwrap().balances.get(0).unwrap().supplied_to_blend, 2000);
+
+    let history = t.client.get_position_history(&user_a);
+    assert_eq!(history.len(), 1);
+    assert_eq!(history.get(0).unwrap(), snapshots.get(0).unwrap());
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_admin_bulk_snapshot_caps_users() {
+    let t = setup();
+    let mut users = Vec::new(&t.env);
+    for _ in 0..(MAX_BULK_SNAPSHOT_USERS + 1) {
+        users.push_back(Address::generate(&t.env));
+    }
+    t.client.admin_bulk_snapshot(&t.admin, &users);
+}
+
+#[test]
+fn test_admin_export_state() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    t.client.supply_to_blend(&user, &token, &1000, &None);
+
+    let state = t.client.admin_export_state(&t.admin);
+    assert_eq!(state.config, read_config(&t));
+    assert_eq!(state.blend_config, read_blend_config(&t));
+    assert_eq!(state.active_users, 1);
+}
+
+#[test]
+fn test_paused_token_blocks_supply_but_allows_withdraw() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let other = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1000, &None);
+    t.client.borrow_against_dust(&user, &token, &100, &0);
+    t.client.admin_pause_token(&t.admin, &token);
+    assert_eq!(dust_events(&t), std::vec![DustEvent::TokenPaused(token.clone())]);
+
+    assert!(t.client.try_supply_to_blend(&user, &token, &100, &None).is_err());
+    assert!(t.client.try_borrow_against_dust(&user, &token, &100, &0).is_err());
+
+    // Other tokens are unaffected, and exits remain open
+    t.client.supply_to_blend(&user, &other, &100, &None);
+    t.client.withdraw_from_blend(&user, &token, &400);
+    t.client.repay_blend_debt(&user, &token, &100);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 600);
+
+    t.client.admin_unpause_token(&t.admin, &token);
+    t.client.supply_to_blend(&user, &token, &100, &None);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 700);
+}
+
+#[test]
+fn test_user_borrow_cap_enforced_and_released() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.admin_set_user_borrow_cap(&t.admin, &user, &1_000);
+
+    t.client.borrow_against_dust(&user, &token, &600, &0);
+    assert_eq!(t.client.get_user_borrow_cap(&user).current_borrow_usd, 600);
+
+    // 500 more would exceed the remaining 400 of headroom
+    assert!(t.client.try_borrow_against_dust(&user, &token, &500, &0).is_err());
+
+    t.client.repay_blend_debt(&user, &token, &200);
+    assert_eq!(t.client.get_user_borrow_cap(&user).current_borrow_usd, 400);
+
+    t.client.borrow_against_dust(&user, &token, &600, &0);
+    let cap = t.client.get_user_borrow_cap(&user);
+    assert_eq!(cap.max_borrow_usd, 1_000);
+    assert_eq!(cap.current_borrow_usd, 1_000);
+}
+
+#[test]
+#[should_panic(expected = "Insufficient collateral")]
+fn test_user_borrow_cap_rejects_over_limit() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.admin_set_user_borrow_cap(&t.admin, &user, &1_000);
+    t.client.borrow_against_dust(&user, &token, &1_001, &0);
+}
+
+#[test]
+fn test_admin_sync_user_balance_matches_blend_position() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    t.client.borrow_against_dust(&user, &token, &400, &0);
+
+    let before = t.client.get_user_balance(&user, &token);
+    assert_eq!(before.supplied_to_blend, 1_000);
+    assert_eq!(before.borrowed_from_blend, 400);
+
+    // Blend liquidated part of the position behind the contract's back
+    t.pool.set_position(&position(&t.env, &[(token.clone(), 700)], &[(token.clone(), 150)]));
+
+    let synced = t.client.admin_sync_user_balance(&t.admin, &user, &token);
+    assert_eq!(synced.supplied_to_blend, 700);
+    assert_eq!(synced.borrowed_from_blend, 150);
+    assert_eq!(
+        dust_events(&t),
+        std::vec![DustEvent::UserBalanceSynced(user.clone(), token.clone(), 1_000, 700, 400, 150)]
+    );
+
+    let after = t.client.get_user_balance(&user, &token);
+    assert_eq!(after, synced);
+
+    let total_supplied: i128 = t.env.as_contract(&t.contract_id, || {
+        t.env.storage().instance().get(&DataKey::TokenTotalSupplied(token.clone())).unwrap()
+    });
+    assert_eq!(total_supplied, 700);
+    assert_eq!(t.client.get_user_borrow_cap(&user).current_borrow_usd, 150);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_admin_sync_user_balance_requires_admin() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.admin_sync_user_balance(&user, &user, &token);
+}
+
+#[test]
+fn test_swap_quote_cache_hit_and_miss() {
+    let t = setup();
+    let token = create_token(&t.env);
+    let other = create_token(&t.env);
+    let router = setup_router(&t, 10100);
+
+    let mut params = arbitrage_params(&t.env, &token, 1_000_000);
+    params.swap_path = Vec::from_array(&t.env, [token.clone(), other, token.clone()]);
+
+    let first = t.client.simulate_arbitrage(&params);
+    assert_eq!(first.estimated_profit, 20_100);
+
+    // Same ledger: the cached quote is used even though the router moved
+    router.set_rate(&10000);
+    assert_eq!(t.client.simulate_arbitrage(&params), first);
+
+    // Next ledger: the stale quote is refreshed from the router
+    t.env.ledger().with_mut(|li| li.sequence_number += 1);
+    let second = t.client.simulate_arbitrage(&params);
+    assert_eq!(second.estimated_profit, 0);
+
+    let cached: (i128, u32) = t.env.as_contract(&t.contract_id, || {
+        let keys: Vec<BytesN<32>> = t.env.storage().temporary().get(&DataKey::SwapQuoteCacheKeys).unwrap();
+        assert_eq!(keys.len(), 1);
+        t.env.storage().temporary().get(&DataKey::SwapQuoteCache(keys.get(0).unwrap())).unwrap()
+    });
+    assert_eq!(cached, (1_000_000, t.env.ledger().sequence()));
+}
+
+#[test]
+fn test_clear_swap_quote_cache_removes_stale_entries() {
+    let t = setup();
+    let token = create_token(&t.env);
+    let other = create_token(&t.env);
+    setup_router(&t, 10100);
+
+    let mut params = arbitrage_params(&t.env, &token, 1_000_000);
+    params.swap_path = Vec::from_array(&t.env, [token.clone(), other, token.clone()]);
+    t.client.simulate_arbitrage(&params);
+
+    // Entries from the current ledger are kept
+    assert_eq!(t.client.clear_swap_quote_cache(), 0);
+
+    t.env.ledger().with_mut(|li| li.sequence_number += 1);
+    assert_eq!(t.client.clear_swap_quote_cache(), 1);
+    assert_eq!(t.client.clear_swap_quote_cache(), 0);
+}
+
+#[test]
+fn test_realized_pnl_positive_on_withdraw_above_cost_basis() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    // 1_000_000 supplied at cost grows to 1_050_000 once the yield is compounded
+    accrue_half_year_yield(&t, &user, &token);
+    t.client.auto_compound(&user, &token);
+
+    // Half the position carries half the cost basis and half the gain
+    t.client.withdraw_from_blend(&user, &token, &525_000);
+    assert_eq!(t.client.get_realized_pnl(&user, &token), 25_000);
+
+    // The rest realizes the remaining gain against the remaining 500_000 of cost basis
+    t.client.withdraw_from_blend(&user, &token, &525_000);
+    assert_eq!(t.client.get_realized_pnl(&user, &token), 50_000);
+}
+
+#[test]
+fn test_realized_pnl_negative_on_repay_above_principal() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.borrow_against_dust(&user, &token, &500, &0);
+    t.client.repay_blend_debt(&user, &token, &200);
+    assert_eq!(t.client.get_realized_pnl(&user, &token), 0);
+
+    t.client.repay_blend_debt(&user, &token, &350);
+    assert_eq!(t.client.get_realized_pnl(&user, &token), -50);
+}
+
+#[test]
+fn test_max_positions_enforced_for_new_tokens_only() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    t.client.admin_set_max_positions(&t.admin, &2);
+    assert_eq!(read_config(&t).max_positions_per_user, 2);
+
+    let token_a = create_token(&t.env);
+    let token_b = create_token(&t.env);
+    t.client.supply_to_blend(&user, &token_a, &100, &None);
+    t.client.supply_to_blend(&user, &token_b, &100, &None);
+
+    // A third token is rejected at the boundary
+    let token_c = create_token(&t.env);
+    assert!(t.client.try_supply_to_blend(&user, &token_c, &100, &None).is_err());
+
+    // Existing positions can still be topped up
+    t.client.supply_to_blend(&user, &token_a, &50, &None);
+    assert_eq!(t.client.get_user_balance(&user, &token_a).supplied_to_blend, 150);
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_admin_set_max_positions_rejects_above_ceiling() {
+    let t = setup();
+    t.client.admin_set_max_positions(&t.admin, &(MAX_POSITIONS_PER_USER_CEILING + 1));
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_admin_set_max_positions_rejects_zero() {
+    let t = setup();
+    t.client.admin_set_max_positions(&t.admin, &0);
+}
+
+#[test]
+fn test_collateral_shortfall_across_ratios() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    // No position: the borrow token itself is the fallback collateral
+    assert_eq!(t.client.get_collateral_shortfall(&user, &debt, &800), 1_000);
+
+    t.client.supply_to_blend(&user, &collateral, &1_000, &None);
+
+    // 1000 collateral at 80% supports up to 800 of debt at the minimum health factor
+    assert_eq!(t.client.get_collateral_shortfall(&user, &debt, &700), 0);
+    assert_eq!(t.client.get_collateral_shortfall(&user, &debt, &800), 0);
+    assert_eq!(t.client.get_collateral_shortfall(&user, &debt, &1_000), 250);
+
+    // Existing debt counts towards the projection
+    t.client.borrow_against_dust(&user, &debt, &400, &0);
+    assert_eq!(t.client.get_collateral_shortfall(&user, &debt, &600), 250);
+
+    // Oracle price of the borrow token is applied: 10_000 XLM at $0.12 is $1_200 of debt
+    let xlm = Address::from_str(&t.env, XLM_TOKEN);
+    assert_eq!(t.client.get_collateral_shortfall(&user, &xlm, &10_000), 1_000);
+}
+
+#[test]
+fn test_initialize_sets_contract_version() {
+    let t = setup();
+
+    let version = t.client.get_contract_version();
+    assert_eq!(version.major, CONTRACT_VERSION_MAJOR);
+    assert_eq!(version.minor, CONTRACT_VERSION_MINOR);
+    assert_eq!(version.patch, CONTRACT_VERSION_PATCH);
+    assert_eq!(version.deployed_at, t.env.ledger().sequence());
+    assert!(t.client.get_version_history().is_empty());
+}
+
+#[test]
+fn test_admin_record_upgrade_appends_history() {
+    let t = setup();
+    let initial = t.client.get_contract_version();
+
+    t.env.ledger().with_mut(|li| li.sequence_number += 100);
+    let upgraded = ContractVersion {
+        major: 1,
+        minor: 1,
+        patch: 0,
+        deployed_at: t.env.ledger().sequence(),
+    };
+
+    // What admin_upgrade records when it installs the authorized hash
+    let wasm_hash = BytesN::from_array(&t.env, &[7; 32]);
+    t.client.admin_authorize_upgrade(&t.admin, &wasm_hash);
+    t.env.as_contract(&t.contract_id, || {
+        t.env.storage().instance().set(&UpgradeKey::InstalledWasmHash, &wasm_hash);
+    });
+    t.client.admin_record_upgrade(&t.admin, &upgraded);
+
+    assert_eq!(t.client.get_contract_version(), upgraded);
+    assert_eq!(t.client.get_version_history(), Vec::from_array(&t.env, [initial]));
+}
+
+fn auction(env: &Env, bid: &[(Address, i128)], lot: &[(Address, i128)]) -> AuctionData {
+    let mut bid_map = Map::new(env);
+    for (token, amount) in bid.iter() {
+        bid_map.set(token.clone(), *amount);
+    }
+    let mut lot_map = Map::new(env);
+    for (token, amount) in lot.iter() {
+        lot_map.set(token.clone(), *amount);
+    }
+    AuctionData {
+        bid: bid_map,
+        lot: lot_map,
+        block: env.ledger().sequence(),
+    }
+}
+
+#[test]
+fn test_fill_bad_debt_auction_forwards_lot() {
+    let t = setup();
+    let caller = Address::generate(&t.env);
+    let auction_id = Address::generate(&t.env);
+    let bid_token = create_token(&t.env);
+    let lot_token = create_token(&t.env);
+
+    t.pool.set_auction(
+        &AUCTION_TYPE_BAD_DEBT,
+        &auction_id,
+        &auction(&t.env, &[(bid_token.clone(), 1_000)], &[(lot_token.clone(), 1_200)]),
+    );
+    mint(&t.env, &lot_token, &t.pool.address, 1_200);
+    mint(&t.env, &bid_token, &caller, 1_000);
+
+    let received = t.client.fill_bad_debt_auction(&caller, &auction_id, &bid_token, &1_000);
+    assert_eq!(received, 1_200);
+    assert_eq!(
+        dust_events(&t),
+        std::vec![DustEvent::BadDebtAuctionFilled(caller.clone(), auction_id.clone(), 1_000, 1_200)]
+    );
+
+    assert_eq!(TokenClient::new(&t.env, &bid_token).balance(&caller), 0);
+    assert_eq!(TokenClient::new(&t.env, &lot_token).balance(&caller), 1_200);
+    assert_eq!(t.client.get_auction_participation(&caller), 1);
+
+    let last = t.pool.submitted().last().unwrap();
+    assert_eq!(last.request_type, REQUEST_FILL_BAD_DEBT_AUCTION);
+    assert_eq!(last.address, auction_id);
+    assert_eq!(last.amount, 1_000);
+}
+
+#[test]
+#[should_panic(expected = "Auction not found")]
+fn test_fill_bad_debt_auction_rejects_unknown_auction() {
+    let t = setup();
+    let caller = Address::generate(&t.env);
+    let bid_token = create_token(&t.env);
+    mint(&t.env, &bid_token, &caller, 1_000);
+
+    t.client.fill_bad_debt_auction(&caller, &Address::generate(&t.env), &bid_token, &1_000);
+}
+
+#[test]
+fn test_fill_interest_auction_returns_backstop_tokens() {
+    let t = setup();
+    let caller = Address::generate(&t.env);
+    let backstop = Address::generate(&t.env);
+    let bid_token = create_token(&t.env);
+    let backstop_token = create_token(&t.env);
+
+    t.client.admin_add_token(&t.admin, &bid_token, &token_config(0), &None);
+    t.client.admin_set_backstop(&t.admin, &backstop);
+    t.pool.set_auction(
+        &AUCTION_TYPE_INTEREST,
+        &backstop,
+        &auction(&t.env, &[(bid_token.clone(), 500)], &[(backstop_token.clone(), 650)]),
+    );
+    mint(&t.env, &backstop_token, &t.pool.address, 650);
+    mint(&t.env, &bid_token, &caller, 500);
+
+    let received = t.client.fill_interest_auction(&caller, &bid_token, &500);
+    assert_eq!(received, 650);
+    assert_eq!(
+        dust_events(&t),
+        std::vec![DustEvent::InterestAuctionFilled(caller.clone(), bid_token.clone(), 500)]
+    );
+    assert_eq!(TokenClient::new(&t.env, &backstop_token).balance(&caller), 650);
+
+    let last = t.pool.submitted().last().unwrap();
+    assert_eq!(last.request_type, REQUEST_FILL_INTEREST_AUCTION);
+    assert_eq!(last.address, backstop);
+}
+
+#[test]
+#[should_panic(expected = "Token not supported")]
+fn test_fill_interest_auction_rejects_unsupported_token() {
+    let t = setup();
+    let caller = Address::generate(&t.env);
+    let bid_token = create_token(&t.env);
+
+    t.client.fill_interest_auction(&caller, &bid_token, &500);
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_fill_interest_auction_rejects_zero_bid() {
+    let t = setup();
+    let caller = Address::generate(&t.env);
+    let bid_token = create_token(&t.env);
+
+    t.client.fill_interest_auction(&caller, &bid_token, &0);
+}
+
+#[test]
+fn test_delete_liquidation_auction_when_healthy() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    // Health factor back to 1.6
+    t.pool.set_position(&position(&t.env, &[(collateral.clone(), 1_000)], &[(debt.clone(), 500)]));
+
+    t.client.delete_liquidation_auction(&user, &collateral);
+    assert_eq!(
+        dust_events(&t),
+        std::vec![DustEvent::LiquidationAuctionDeleted(user.clone(), collateral.clone())]
+    );
+
+    let last = t.pool.submitted().last().unwrap();
+    assert_eq!(last.request_type, REQUEST_DELETE_LIQUIDATION_AUCTION);
+    assert_eq!(last.address, collateral);
+    assert_eq!(last.amount, 0);
+}
+
+#[test]
+#[should_panic(expected = "Health factor too low")]
+fn test_delete_liquidation_auction_rejects_unhealthy_position() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.pool.set_position(&position(&t.env, &[(collateral.clone(), 1_000)], &[(debt.clone(), 900)]));
+    t.client.delete_liquidation_auction(&user, &collateral);
+}
+
+#[test]
+fn test_user_positions_page_boundaries() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let tokens = [create_token(&t.env), create_token(&t.env), create_token(&t.env)];
+
+    for token in tokens.iter() {
+        t.env.ledger().with_mut(|li| li.timestamp += 10);
+        t.client.supply_to_blend(&user, token, &100, &None);
+    }
+    assert_eq!(t.client.get_user_position_count(&user), 3);
+
+    // Most recently updated first
+    let page = t.client.get_user_positions_page(&user, &0, &2);
+    assert_eq!(page.len(), 2);
+    assert_eq!(page.get(0).unwrap().token, tokens[2]);
+    assert_eq!(page.get(1).unwrap().token, tokens[1]);
+
+    // Offset at the last element, limit larger than what remains
+    let page = t.client.get_user_positions_page(&user, &2, &10);
+    assert_eq!(page.len(), 1);
+    assert_eq!(page.get(0).unwrap().token, tokens[0]);
+
+    assert!(t.client.get_user_positions_page(&user, &3, &10).is_empty());
+    assert!(t.client.get_user_positions_page(&user, &u32::MAX, &u32::MAX).is_empty());
+}
+
+#[test]
+fn test_auto_deleverage_restores_health_above_floor() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let config = AutoDeleverageConfig {
+        enabled: true,
+        floor_health_factor: HEALTH_FACTOR_SCALE * 95 / 100,
+        deleverage_pct_bps: 5000,
+    };
+    t.client.set_auto_deleverage_config(&user, &config);
+    assert_eq!(t.client.get_auto_deleverage_config(&user), Some(config.clone()));
+
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+
+    // 900 of debt against 1000 of collateral puts the health factor at 0.888
+    t.client.borrow_against_dust(&user, &token, &900, &0);
+
+    let new_health_factor = 550 * 8000 * HEALTH_FACTOR_SCALE / 450 / 10000;
+    assert!(new_health_factor > config.floor_health_factor);
+    assert_eq!(
+        dust_events(&t).last(),
+        Some(&DustEvent::AutoDeleveraged(user.clone(), new_health_factor))
+    );
+
+    // Half the debt is repaid out of the collateral: 550 supplied, 450 borrowed
+    let balance = t.client.get_user_balance(&user, &token);
+    assert_eq!(balance.supplied_to_blend, 550);
+    assert_eq!(balance.borrowed_from_blend, 450);
+
+    let submitted = t.pool.submitted();
+    let repay = submitted.get(submitted.len() - 1).unwrap();
+    let withdraw = submitted.get(submitted.len() - 2).unwrap();
+    assert_eq!((withdraw.request_type, withdraw.amount), (REQUEST_WITHDRAW_COLLATERAL, 450));
+    assert_eq!((repay.request_type, repay.amount), (REQUEST_REPAY, 450));
+}
+
+#[test]
+fn test_auto_deleverage_sells_collateral_to_repay_other_token_debt() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+    let router = setup_router(&t, 10000);
+
+    t.client.set_auto_deleverage_config(&user, &AutoDeleverageConfig {
+        enabled: true,
+        floor_health_factor: HEALTH_FACTOR_SCALE * 95 / 100,
+        deleverage_pct_bps: 5000,
+    });
+
+    mint(&t.env, &collateral, &t.pool.address, 1_000);
+    mint(&t.env, &debt, &t.pool.address, 900);
+    mint(&t.env, &debt, &router.address, 450);
+
+    t.client.supply_to_blend(&user, &collateral, &1_000, &None);
+    t.pool.set_move_tokens(&true);
+    t.client.borrow_against_dust(&user, &debt, &900, &0);
+
+    let new_health_factor = 550 * 8000 * HEALTH_FACTOR_SCALE / 450 / 10000;
+    assert_eq!(
+        dust_events(&t).last(),
+        Some(&DustEvent::AutoDeleveraged(user.clone(), new_health_factor))
+    );
+
+    // Half of the debt is repaid by selling collateral; half of the collateral was on offer
+    assert_eq!(t.client.get_user_balance(&user, &collateral).supplied_to_blend, 550);
+    assert_eq!(t.client.get_user_balance(&user, &debt).borrowed_from_blend, 450);
+    assert_eq!(TokenClient::new(&t.env, &collateral).balance(&router.address), 450);
+
+    let submitted = t.pool.submitted();
+    let withdraw = submitted.get(submitted.len() - 2).unwrap();
+    let repay = submitted.last().unwrap();
+    assert_eq!((withdraw.request_type, withdraw.address, withdraw.amount), (REQUEST_WITHDRAW_COLLATERAL, collateral.clone(), 450));
+    assert_eq!((repay.request_type, repay.address, repay.amount), (REQUEST_REPAY, debt.clone(), 450));
+}
+
+#[test]
+fn test_auto_deleverage_without_matching_debt_changes_nothing() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.set_auto_deleverage_config(&user, &AutoDeleverageConfig {
+        enabled: true,
+        floor_health_factor: HEALTH_FACTOR_SCALE * 95 / 100,
+        deleverage_pct_bps: 5000,
+    });
+
+    // Cross-token debt with no router to sell through leaves nothing to do
+    t.client.supply_to_blend(&user, &collateral, &1_000, &None);
+    t.client.borrow_against_dust(&user, &debt, &900, &0);
+    assert!(!dust_events(&t).iter().any(|event| matches!(event, DustEvent::AutoDeleveraged(..))));
+
+    assert_eq!(t.client.get_user_balance(&user, &collateral).supplied_to_blend, 1_000);
+    assert_eq!(t.client.get_user_balance(&user, &debt).borrowed_from_blend, 900);
+}
+
+#[test]
+fn test_auto_deleverage_disabled_leaves_position() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.set_auto_deleverage_config(&user, &AutoDeleverageConfig {
+        enabled: false,
+        floor_health_factor: HEALTH_FACTOR_SCALE * 95 / 100,
+        deleverage_pct_bps: 5000,
+    });
+
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    t.client.borrow_against_dust(&user, &token, &900, &0);
+
+    let balance = t.client.get_user_balance(&user, &token);
+    assert_eq!(balance.supplied_to_blend, 1_000);
+    assert_eq!(balance.borrowed_from_blend, 900);
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_auto_deleverage_floor_must_be_below_warning() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+
+    t.client.set_auto_deleverage_config(&user, &AutoDeleverageConfig {
+        enabled: true,
+        floor_health_factor: read_blend_config(&t).warning_health_factor,
+        deleverage_pct_bps: 5000,
+    });
+}
+
+#[test]
+fn test_supply_uses_submit_with_allowance_when_flagged() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let standard = create_token(&t.env);
+    let allowance_only = create_token(&t.env);
+
+    t.client.admin_add_token(&t.admin, &standard, &token_config(0), &None);
+    t.client.admin_add_token(&t.admin, &allowance_only, &TokenConfig {
+        use_allowance_submit: true,
+        ..token_config(0)
+    }, &None);
+
+    t.client.supply_to_blend(&user, &standard, &100, &None);
+    assert!(t.pool.allowance_submitted().is_empty());
+    assert_eq!(t.pool.submitted().len(), 1);
+
+    t.client.supply_to_blend(&user, &allowance_only, &200, &None);
+    let allowance_submitted = t.pool.allowance_submitted();
+    assert_eq!(allowance_submitted.len(), 1);
+    assert_eq!(allowance_submitted.get(0).unwrap().address, allowance_only);
+    assert_eq!(allowance_submitted.get(0).unwrap().amount, 200);
+}
+
+#[test]
+fn test_supported_token_views_track_add_and_remove() {
+    let t = setup();
+    let token_a = create_token(&t.env);
+    let token_b = create_token(&t.env);
+    let token_c = create_token(&t.env);
+
+    assert_eq!(t.client.get_supported_token_count(), 0);
+    assert!(t.client.get_supported_tokens(&0, &10).is_empty());
+
+    t.client.admin_add_token(&t.admin, &token_a, &token_config(0), &None);
+    t.client.admin_add_token(&t.admin, &token_b, &token_config(500), &None);
+    t.client.admin_add_token(&t.admin, &token_c, &token_config(0), &None);
+    assert_eq!(t.client.get_supported_token_count(), 3);
+    assert_eq!(
+        t.client.get_supported_tokens(&1, &10),
+        Vec::from_array(&t.env, [token_b.clone(), token_c.clone()])
+    );
+    assert_eq!(t.client.get_token_config(&token_b), Some(token_config(500)));
+
+    t.client.admin_remove_token(&t.admin, &token_b);
+    assert_eq!(t.client.get_supported_token_count(), 2);
+    assert_eq!(
+        t.client.get_supported_tokens(&0, &1),
+        Vec::from_array(&t.env, [token_a.clone()])
+    );
+    assert!(t.client.get_supported_tokens(&2, &10).is_empty());
+    assert_eq!(t.client.get_token_config(&token_b), None);
+}
+
+fn setup_oracle<'a>(t: &TestSetup<'a>) -> MockOracleClient<'a> {
+    let oracle_id = Address::from_str(&t.env, BLEND_ORACLE_MOCK);
+    t.env.register_at(&oracle_id, MockOracle, ());
+    MockOracleClient::new(&t.env, &oracle_id)
+}
+
+#[test]
+fn test_oracle_price_outside_bounds_rejected() {
+    let t = setup();
+    let oracle = setup_oracle(&t);
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.admin_add_token(&t.admin, &token, &token_config(0), &None);
+    t.client.admin_update_token_bounds(&t.admin, &token, &OracleBounds {
+        min_price: 500_000,
+        max_price: 2_000_000,
+    });
+
+    oracle.set_price(&token, &1_500_000);
+    t.client.supply_to_blend(&user, &token, &100, &None);
+    // TVL is valued at the oracle price
+    assert_eq!(t.client.get_stats().0, 150);
+
+    // Runaway oracle
+    t.env.ledger().with_mut(|li| li.sequence_number += 1);
+    oracle.set_price(&token, &50_000_000);
+    assert!(t.client.try_supply_to_blend(&user, &token, &100, &None).is_err());
+}
+
+#[test]
+#[should_panic(expected = "Oracle error")]
+fn test_oracle_price_jump_against_twap_rejected() {
+    let t = setup();
+    let oracle = setup_oracle(&t);
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.admin_set_max_price_change(&t.admin, &1000);
+
+    oracle.set_price(&token, &1_000_000);
+    t.client.supply_to_blend(&user, &token, &100, &None);
+
+    // +5% stays within the 10% band
+    t.env.ledger().with_mut(|li| li.sequence_number += 1);
+    oracle.set_price(&token, &1_050_000);
+    t.client.supply_to_blend(&user, &token, &100, &None);
+
+    // +50% in one ledger is rejected
+    t.env.ledger().with_mut(|li| li.sequence_number += 1);
+    oracle.set_price(&token, &1_500_000);
+    t.client.supply_to_blend(&user, &token, &100, &None);
+}
+
+#[test]
+fn test_insurance_fund_donations_and_admin_use() {
+    let t = setup();
+    let donor_a = Address::generate(&t.env);
+    let donor_b = Address::generate(&t.env);
+    let recipient = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    mint(&t.env, &token, &donor_a, 1_000);
+    mint(&t.env, &token, &donor_b, 500);
+
+    t.client.donate_to_insurance_fund(&donor_a, &token, &300);
+    t.client.donate_to_insurance_fund(&donor_a, &token, &200);
+    assert_eq!(
+        dust_events(&t),
+        std::vec![DustEvent::InsuranceDonation(donor_a.clone(), token.clone(), 200)]
+    );
+    t.client.donate_to_insurance_fund(&donor_b, &token, &500);
+
+    assert_eq!(t.client.get_insurance_fund(&token), 1_000);
+    assert_eq!(t.client.get_insurance_donations(&donor_a).get(token.clone()), Some(500));
+    assert_eq!(t.client.get_insurance_donations(&donor_b).get(token.clone()), Some(500));
+
+    let reason = Symbol::new(&t.env, "bad_debt");
+    t.client.admin_use_insurance_fund(&t.admin, &token, &400, &recipient, &reason);
+    assert_eq!(
+        dust_events(&t),
+        std::vec![DustEvent::InsuranceUsed(t.admin.clone(), token.clone(), 400, reason.clone())]
+    );
+    assert_eq!(t.client.get_insurance_fund(&token), 600);
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&recipient), 400);
+
+    // Cannot draw more than the fund holds
+    assert!(t.client.try_admin_use_insurance_fund(&t.admin, &token, &601, &recipient, &reason).is_err());
+}
+
+#[test]
+fn test_borrow_and_supply_rates_across_utilization() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.admin_add_token(&t.admin, &token, &TokenConfig {
+        reserve_factor_bps: 1000,
+        ..token_config(0)
+    }, &None);
+
+    // No supply yet
+    assert_eq!(t.client.compute_borrow_rate(&token), 0);
+    assert_eq!(t.client.compute_supply_rate(&token), 0);
+
+    // 0% utilization
+    t.client.supply_to_blend(&user, &token, &10_000, &None);
+    assert_eq!(t.client.compute_borrow_rate(&token), DEFAULT_BASE_RATE_BPS);
+    assert_eq!(t.client.compute_supply_rate(&token), 0);
+
+    // 50% utilization: halfway up the first slope
+    t.client.borrow_against_dust(&user, &token, &5_000, &0);
+    assert_eq!(t.client.compute_borrow_rate(&token), 250);
+    assert_eq!(t.client.compute_supply_rate(&token), 250 * 5000 / 10000 * 9000 / 10000);
+
+    // At the kink
+    t.client.borrow_against_dust(&user, &token, &3_000, &0);
+    assert_eq!(t.client.compute_borrow_rate(&token), DEFAULT_RATE_SLOPE1_BPS);
+    assert_eq!(t.client.compute_supply_rate(&token), 400 * 8000 / 10000 * 9000 / 10000);
+
+    // 100% utilization: top of the second slope
+    t.client.borrow_against_dust(&user, &token, &2_000, &0);
+    assert_eq!(t.client.compute_borrow_rate(&token), DEFAULT_RATE_SLOPE1_BPS + DEFAULT_RATE_SLOPE2_BPS);
+    assert_eq!(t.client.compute_supply_rate(&token), 6400 * 9000 / 10000);
+}
+
+#[test]
+fn test_keeper_health_check_pays_reward_with_cooldown() {
+    let t = setup();
+    let keeper = Address::generate(&t.env);
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let stablecoin = create_token(&t.env);
+    let funder = Address::generate(&t.env);
+
+    t.client.admin_set_keeper_reward(&t.admin, &KeeperReward { token: stablecoin.clone(), amount: 25 });
+    mint(&t.env, &stablecoin, &funder, 40);
+    t.client.fund_keeper_reward_pool(&funder, &40);
+
+    // 900 of debt against 1000 of collateral: health factor 0.888
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    t.client.borrow_against_dust(&user, &token, &900, &0);
+
+    let health_factor = t.client.check_position_health(&keeper, &user);
+    assert_eq!(health_factor, 1_000 * 8000 * HEALTH_FACTOR_SCALE / 900 / 10000);
+    assert_eq!(
+        dust_events(&t),
+        std::vec![DustEvent::HealthCheckTriggered(keeper.clone(), user.clone(), health_factor)]
+    );
+    assert_eq!(TokenClient::new(&t.env, &stablecoin).balance(&keeper), 25);
+
+    assert_eq!(t.client.get_keeper_reward_pool(), 15);
+
+    // Cooldown blocks repeated rewards for the position, including from other keepers
+    t.env.ledger().with_mut(|li| li.sequence_number += KEEPER_COOLDOWN_LEDGERS - 1);
+    assert!(t.client.try_check_position_health(&keeper, &user).is_err());
+    assert!(t.client.try_check_position_health(&Address::generate(&t.env), &user).is_err());
+
+    // The pool only has 15 left to pay out
+    t.env.ledger().with_mut(|li| li.sequence_number += 1);
+    t.client.check_position_health(&keeper, &user);
+    assert_eq!(TokenClient::new(&t.env, &stablecoin).balance(&keeper), 40);
+    assert_eq!(t.client.get_keeper_reward_pool(), 0);
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_keeper_health_check_rejects_healthy_position() {
+    let t = setup();
+    let keeper = Address::generate(&t.env);
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    t.client.borrow_against_dust(&user, &token, &100, &0);
+    t.client.check_position_health(&keeper, &user);
+}
+
+#[test]
+fn test_scheduled_compound_rejects_early_and_compounds_on_time() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1_000_000, &None);
+    set_supply_rate(&t, &token, 1000);
+    t.client.set_compound_schedule(&user, &token, &17_280);
+
+    // Too early
+    t.env.ledger().with_mut(|li| li.sequence_number += 17_279);
+    assert!(t.client.try_execute_scheduled_compound(&user, &token).is_err());
+
+    // On time, half a year of 10% APY has accrued
+    t.env.ledger().with_mut(|li| {
+        li.sequence_number += 1;
+        li.timestamp += SECONDS_PER_YEAR as u64 / 2;
+    });
+    let compounded = t.client.execute_scheduled_compound(&user, &token);
+    assert_eq!(compounded, 50_000);
+
+    let balance = t.client.get_user_balance(&user, &token);
+    assert_eq!(balance.supplied_to_blend, 1_050_000);
+    assert_eq!(balance.accrued_interest, 0);
+    assert_eq!(
+        t.client.get_compound_schedule(&user, &token).unwrap().last_compound_ledger,
+        t.env.ledger().sequence()
+    );
+
+    // The next run waits a full interval again
+    assert!(t.client.try_execute_scheduled_compound(&user, &token).is_err());
+}
+
+fn permit_signer(env: &Env, seed: u8) -> (ed25519_dalek::SigningKey, Address) {
+    use soroban_sdk::xdr::{AccountId, PublicKey, ScAddress, Uint256};
+
+    let signing_key = ed25519_dalek::SigningKey::from_bytes(&[seed; 32]);
+    let account = ScAddress::Account(AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(
+        signing_key.verifying_key().to_bytes(),
+    ))));
+    (signing_key, Address::try_from_val(env, &account).unwrap())
+}
+
+fn sign_permit(t: &TestSetup, key: &ed25519_dalek::SigningKey, token: &Address, amount: i128, deadline: u32, nonce: u64) -> BytesN<64> {
+    use ed25519_dalek::Signer;
+    use soroban_sdk::xdr::ToXdr;
+
+    let message = (t.contract_id.clone(), token.clone(), amount, deadline, nonce).to_xdr(&t.env);
+    let message: std::vec::Vec<u8> = message.iter().collect();
+    BytesN::from_array(&t.env, &key.sign(&message).to_bytes())
+}
+
+#[test]
+fn test_supply_with_permit_and_replay_protection() {
+    let t = setup();
+    let (key, user) = permit_signer(&t.env, 7);
+    let token = create_token(&t.env);
+    let deadline = t.env.ledger().sequence() + 100;
+
+    let signature = sign_permit(&t, &key, &token, 500, deadline, 0);
+    t.client.supply_with_permit(&user, &token, &500, &deadline, &signature);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 500);
+    assert_eq!(t.client.get_nonce(&user), 1);
+
+    // The same signature is bound to the consumed nonce
+    assert!(t.client.try_supply_with_permit(&user, &token, &500, &deadline, &signature).is_err());
+
+    let signature = sign_permit(&t, &key, &token, 300, deadline, 1);
+    t.client.supply_with_permit(&user, &token, &300, &deadline, &signature);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 800);
+}
+
+#[test]
+fn test_supply_with_permit_rejects_bad_signature() {
+    let t = setup();
+    let (_, user) = permit_signer(&t.env, 7);
+    let (other_key, _) = permit_signer(&t.env, 8);
+    let token = create_token(&t.env);
+    let deadline = t.env.ledger().sequence() + 100;
+
+    // Signed by the wrong key
+    let signature = sign_permit(&t, &other_key, &token, 500, deadline, 0);
+    assert!(t.client.try_supply_with_permit(&user, &token, &500, &deadline, &signature).is_err());
+    assert_eq!(t.client.get_nonce(&user), 0);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_supply_with_permit_rejects_expired_deadline() {
+    let t = setup();
+    let (key, user) = permit_signer(&t.env, 7);
+    let token = create_token(&t.env);
+    let deadline = t.env.ledger().sequence() + 10;
+
+    let signature = sign_permit(&t, &key, &token, 500, deadline, 0);
+    t.env.ledger().with_mut(|li| li.sequence_number += 11);
+    t.client.supply_with_permit(&user, &token, &500, &deadline, &signature);
+}
+
+#[test]
+fn test_withdraw_with_deadline() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+
+    let max_ledger = t.env.ledger().sequence() + 10;
+    t.client.withdraw_with_deadline(&user, &token, &400, &max_ledger);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 600);
+
+    // Delayed past the deadline
+    t.env.ledger().with_mut(|li| li.sequence_number += 11);
+    assert!(t.client.try_withdraw_with_deadline(&user, &token, &400, &max_ledger).is_err());
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 600);
+}
+
+#[test]
+#[should_panic(expected = "Deadline exceeded")]
+fn test_flash_loan_arbitrage_rejects_expired_deadline() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let mut params = arbitrage_params(&t.env, &token, 1_000);
+    params.deadline = Some(t.env.ledger().sequence() + 5);
+
+    t.env.ledger().with_mut(|li| li.sequence_number += 6);
+    t.client.flash_loan_arbitrage(&user, &params);
+}
+
+#[test]
+fn test_active_liquidation_auctions_tracked_until_filled() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let liquidator = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &collateral, &1000, &None);
+    t.client.borrow_against_dust(&user, &debt, &900, &0);
+    mint(&t.env, &debt, &liquidator, 900);
+    mint(&t.env, &collateral, &t.pool.address, 1000);
+    t.pool.set_move_tokens(&true);
+
+    let start_ledger = t.env.ledger().sequence();
+    t.client.fill_liquidation_for_user(&liquidator, &user, &collateral, &debt, &500);
+
+    let auctions = t.client.get_active_liquidation_auctions();
+    assert_eq!(auctions, Vec::from_array(&t.env, [LiquidationAuction {
+        user: user.clone(),
+        collateral_token: collateral.clone(),
+        debt_token: debt.clone(),
+        collateral_amount: 475,
+        debt_amount: 400,
+        start_ledger,
+    }]));
+
+    // Still under water, so filling the rest of the debt closes the auction
+    t.env.ledger().with_mut(|li| li.sequence_number += 5);
+    t.client.fill_liquidation_for_user(&liquidator, &user, &collateral, &debt, &400);
+    assert!(t.client.get_active_liquidation_auctions().is_empty());
+}
+
+#[test]
+fn test_auction_state_synced_from_blend_position() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let liquidator = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &collateral, &1000, &None);
+    t.client.borrow_against_dust(&user, &debt, &900, &0);
+    t.pool.set_position(&position(&t.env, &[(collateral.clone(), 1000)], &[(debt.clone(), 900)]));
+    mint(&t.env, &debt, &liquidator, 500);
+    mint(&t.env, &collateral, &t.pool.address, 1000);
+    t.pool.set_move_tokens(&true);
+    t.client.fill_liquidation_for_user(&liquidator, &user, &collateral, &debt, &500);
+    assert_eq!(t.client.get_active_liquidation_auctions().len(), 1);
+
+    // Blend settled the debt side outside this contract
+    t.pool.set_move_tokens(&false);
+    t.pool.set_position(&position(&t.env, &[(collateral.clone(), 1000)], &[]));
+    t.client.supply_to_blend(&user, &collateral, &100, &None);
+    assert!(t.client.get_active_liquidation_auctions().is_empty());
+}
+
+#[test]
+fn test_oracle_override_preferred_and_removable() {
+    let t = setup();
+    let primary = setup_oracle(&t);
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let secondary_id = t.env.register(MockOracle, ());
+    let secondary = MockOracleClient::new(&t.env, &secondary_id);
+    primary.set_price(&token, &2_000_000);
+    secondary.set_price(&token, &3_000_000);
+
+    t.client.admin_set_oracle_override(&t.admin, &token, &secondary_id);
+    assert_eq!(
+        dust_events(&t),
+        std::vec![DustEvent::OracleOverrideSet(token.clone(), secondary_id.clone())]
+    );
+
+    // Valued at the override oracle's $3
+    t.client.supply_to_blend(&user, &token, &100, &None);
+    assert_eq!(t.client.get_stats().0, 300);
+
+    t.client.admin_remove_oracle_override(&t.admin, &token);
+    assert_eq!(dust_events(&t), std::vec![DustEvent::OracleOverrideRemoved(token.clone())]);
+
+    // Back on the primary oracle's $2
+    t.client.supply_to_blend(&user, &token, &100, &None);
+    assert_eq!(t.client.get_stats().0, 500);
+}
+
+#[test]
+#[should_panic(expected = "Stale oracle data")]
+fn test_oracle_override_subject_to_staleness_check() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let secondary_id = t.env.register(MockOracle, ());
+    let secondary = MockOracleClient::new(&t.env, &secondary_id);
+    secondary.set_price(&token, &1_000_000);
+    secondary.set_last_updated(&(t.env.ledger().timestamp()));
+    t.client.admin_set_oracle_override(&t.admin, &token, &secondary_id);
+
+    t.env.ledger().with_mut(|li| li.timestamp += MAX_ORACLE_PRICE_AGE + 1);
+    t.client.supply_to_blend(&user, &token, &100, &None);
+}
+
+#[test]
+fn test_flash_loan_liquidation_returns_collateral_profit() {
+    let t = setup();
+    let caller = Address::generate(&t.env);
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+    let router = setup_router(&t, 10000);
+
+    t.client.supply_to_blend(&user, &collateral, &1000, &None);
+    t.client.borrow_against_dust(&user, &debt, &900, &0);
+    mint(&t.env, &debt, &router.address, 500);
+    // The mock flash loan only records its legs, so the withdrawn collateral is credited up front
+    mint(&t.env, &collateral, &t.contract_id, 525);
+
+    // 525 collateral seized; 500 is swapped to repay the flash loan
+    let submitted_before = t.pool.submitted().len();
+    let profit = t.client.flash_loan_liquidation(&caller, &user, &debt, &collateral, &500);
+    assert_eq!(profit, 25);
+
+    let collateral_token = TokenClient::new(&t.env, &collateral);
+    assert_eq!(collateral_token.balance(&caller), 25);
+    assert_eq!(collateral_token.balance(&router.address), 500);
+    assert_eq!(collateral_token.balance(&t.contract_id), 0);
+    assert_eq!(TokenClient::new(&t.env, &debt).balance(&t.contract_id), 500);
+
+    assert_eq!(t.client.get_user_balance(&user, &debt).borrowed_from_blend, 400);
+    assert_eq!(t.client.get_user_balance(&user, &collateral).supplied_to_blend, 475);
+
+    // One batch on the contract's own position: borrow, fill, repay
+    let submitted = t.pool.submitted();
+    assert_eq!(submitted.len(), submitted_before + 4);
+    let legs: std::vec::Vec<(u32, Address, i128)> = submitted.iter()
+        .skip(submitted_before as usize)
+        .map(|r| (r.request_type, r.address, r.amount))
+        .collect();
+    assert_eq!(legs, std::vec![
+        (REQUEST_BORROW, debt.clone(), 500),
+        (REQUEST_REPAY, debt.clone(), 500),
+        (REQUEST_WITHDRAW_COLLATERAL, collateral.clone(), 525),
+        (REQUEST_REPAY, debt.clone(), 500),
+    ]);
+}
+
+#[test]
+#[should_panic(expected = "Profit below threshold")]
+fn test_flash_loan_liquidation_rejects_unprofitable_swap() {
+    let t = setup();
+    let caller = Address::generate(&t.env);
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+    // Swapping collateral loses 10%, more than the 5% bonus
+    setup_router(&t, 9000);
+
+    t.client.supply_to_blend(&user, &collateral, &1000, &None);
+    t.client.borrow_against_dust(&user, &debt, &900, &0);
+
+    t.client.flash_loan_liquidation(&caller, &user, &debt, &collateral, &500);
+}
+
+#[test]
+#[should_panic(expected = "Position not liquidatable")]
+fn test_flash_loan_liquidation_rejects_healthy_position() {
+    let t = setup();
+    let caller = Address::generate(&t.env);
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &collateral, &1000, &None);
+    t.client.borrow_against_dust(&user, &debt, &500, &0);
+    t.client.flash_loan_liquidation(&caller, &user, &debt, &collateral, &500);
+}
+
+#[test]
+fn test_debt_repayment_schedule_amortizes_full_debt() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    // 50% utilization puts the borrow rate at 250 bps
+    t.client.supply_to_blend(&user, &token, &2_000_000, &None);
+    t.client.borrow_against_dust(&user, &token, &1_000_000, &0);
+
+    let interval = (LEDGERS_PER_YEAR / 4) as u32;
+    let schedule = t.client.get_debt_repayment_schedule(&user, &token, &3, &interval);
+    assert_eq!(schedule.len(), 3);
+
+    let total_principal: i128 = schedule.iter().map(|i| i.principal).sum();
+    assert_eq!(total_principal, 1_000_000);
+    assert_eq!(schedule.last().unwrap().remaining_balance, 0);
+    assert_eq!(schedule.last().unwrap().principal, 333_334);
+
+    // First quarter accrues 2.5% / 4 on the full balance
+    let first = schedule.get(0).unwrap();
+    assert_eq!(first.interest, 1_000_000 * 250 / 10000 / 4);
+    assert_eq!(first.total, first.principal + first.interest);
+    assert_eq!(first.ledger, t.env.ledger().sequence() + interval);
+
+    // Installments are capped at one year of weekly payments
+    let capped = t.client.get_debt_repayment_schedule(&user, &token, &100, &120_960);
+    assert_eq!(capped.len(), MAX_REPAYMENT_INSTALLMENTS);
+    assert_eq!(capped.last().unwrap().remaining_balance, 0);
+}
+
+#[test]
+fn test_admin_actions_recorded_in_audit_log() {
+    let t = setup();
+    let token = create_token(&t.env);
+
+    t.client.admin_add_token(&t.admin, &token, &token_config(0), &None);
+    t.client.set_paused(&t.admin, &true);
+
+    assert_eq!(t.client.get_audit_log_count(), 3);
+    let log = t.client.get_audit_log(&0, &10);
+    assert_eq!(log.get(0).unwrap().action, Symbol::new(&t.env, "initialize"));
+    assert_eq!(log.get(1).unwrap().action, Symbol::new(&t.env, "admin_add_token"));
+
+    let entry = log.get(2).unwrap();
+    assert_eq!(entry.action, Symbol::new(&t.env, "set_paused"));
+    assert_eq!(entry.actor, t.admin);
+    assert_eq!(entry.ledger, t.env.ledger().sequence());
+    let expected: Val = (true,).into_val(&t.env);
+    assert_eq!(entry.params_hash, t.env.crypto().sha256(&expected.to_xdr(&t.env)).to_bytes());
+}
+
+#[test]
+fn test_audit_log_ring_buffer_evicts_oldest() {
+    let t = setup();
+
+    for i in 0..AUDIT_LOG_CAPACITY {
+        t.client.set_paused(&t.admin, &(i % 2 == 0));
+    }
+    assert_eq!(t.client.get_audit_log_count(), AUDIT_LOG_CAPACITY);
+
+    // The initialize entry was evicted, leaving the first set_paused call as the oldest
+    let oldest = t.client.get_audit_log(&0, &1).get(0).unwrap();
+    assert_eq!(oldest.action, Symbol::new(&t.env, "set_paused"));
+    let expected: Val = (true,).into_val(&t.env);
+    assert_eq!(oldest.params_hash, t.env.crypto().sha256(&expected.to_xdr(&t.env)).to_bytes());
+
+    t.client.admin_set_tvl_cap(&t.admin, &1_000_000);
+    let newest = t.client.get_audit_log(&(AUDIT_LOG_CAPACITY - 1), &1).get(0).unwrap();
+    assert_eq!(newest.action, Symbol::new(&t.env, "admin_set_tvl_cap"));
+}
+
+#[test]
+fn test_audit_log_pagination() {
+    let t = setup();
+    for _ in 0..4 {
+        t.client.set_paused(&t.admin, &false);
+    }
+
+    assert_eq!(t.client.get_audit_log(&0, &2).len(), 2);
+    assert_eq!(t.client.get_audit_log(&3, &10).len(), 2);
+    assert_eq!(t.client.get_audit_log(&5, &10).len(), 0);
+    assert_eq!(t.client.get_audit_log(&1, &1).get(0).unwrap().action, Symbol::new(&t.env, "set_paused"));
+}
+
+#[test]
+fn test_supply_mode_routes_deposit_only_supply() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    assert_eq!(t.client.get_supply_mode(&user, &token), SupplyMode::Collateral);
+    t.client.supply_to_blend(&user, &token, &1000, &None);
+
+    t.client.set_supply_mode(&user, &token, &SupplyMode::DepositOnly);
+    t.client.supply_to_blend(&user, &token, &500, &None);
+
+    let submitted = t.pool.submitted();
+    assert_eq!(submitted.get(0).unwrap().request_type, REQUEST_DEPOSIT_COLLATERAL);
+    assert_eq!(submitted.get(1).unwrap().request_type, REQUEST_DEPOSIT);
+
+    let balance = t.client.get_user_balance(&user, &token);
+    assert_eq!(balance.supplied_to_blend, 1000);
+    assert_eq!(balance.deposited_to_blend, 500);
+
+    // Withdrawals drain the deposit-only share before touching collateral
+    t.client.withdraw_from_blend(&user, &token, &700);
+    let submitted = t.pool.submitted();
+    let withdraw = submitted.get(2).unwrap();
+    assert_eq!((withdraw.request_type, withdraw.amount), (REQUEST_WITHDRAW, 500));
+    let withdraw = submitted.get(3).unwrap();
+    assert_eq!((withdraw.request_type, withdraw.amount), (REQUEST_WITHDRAW_COLLATERAL, 200));
+
+    let balance = t.client.get_user_balance(&user, &token);
+    assert_eq!(balance.supplied_to_blend, 800);
+    assert_eq!(balance.deposited_to_blend, 0);
+}
+
+#[test]
+fn test_deposit_only_supply_excluded_from_health_factor() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1000, &None);
+    t.client.borrow_against_dust(&user, &token, &400, &0);
+    let health_factor = |t: &TestSetup| t.env.as_contract(&t.contract_id, || {
+        DustAggregator::calculate_user_health_factor(&t.env, &user)
+    });
+    let before = health_factor(&t);
+
+    t.client.set_supply_mode(&user, &token, &SupplyMode::DepositOnly);
+    t.client.supply_to_blend(&user, &token, &1000, &None);
+    assert_eq!(health_factor(&t), before);
+    assert_eq!(before, 1000 * 80 / 100 * HEALTH_FACTOR_SCALE / 400);
+}
+
+#[test]
+fn test_admin_pause_records_reason() {
+    let t = setup();
+    assert!(!t.client.is_paused());
+
+    t.env.ledger().with_mut(|li| li.sequence_number = 500);
+    t.client.admin_pause(&t.admin, &Symbol::new(&t.env, "oracle_outage"));
+
+    let state = t.client.get_pause_state();
+    assert!(state.paused);
+    assert!(t.client.is_paused());
+    assert_eq!(state.reason, Symbol::new(&t.env, "oracle_outage"));
+    assert_eq!(state.paused_at, 500);
+    assert_eq!(state.paused_by, t.admin);
+
+    t.client.admin_unpause(&t.admin);
+    assert!(!t.client.is_paused());
+    assert_eq!(t.client.get_pause_state().reason, Symbol::new(&t.env, ""));
+}
+
+#[test]
+fn test_emergency_pause_sets_reason() {
+    let t = setup();
+    let emergency_admin = Address::generate(&t.env);
+    t.client.set_emergency_admin(&t.admin, &emergency_admin);
+
+    t.client.emergency_pause(&emergency_admin);
+    let state = t.client.get_pause_state();
+    assert_eq!(state.reason, Symbol::new(&t.env, "emergency"));
+    assert_eq!(state.paused_by, emergency_admin);
+}
+
+#[test]
+fn test_admin_risk_report_aggregates_active_users() {
+    let t = setup();
+    let token_a = create_token(&t.env);
+    let token_b = create_token(&t.env);
+    let healthy = Address::generate(&t.env);
+    let unhealthy = Address::generate(&t.env);
+    let lender = Address::generate(&t.env);
+
+    t.env.as_contract(&t.contract_id, || {
+        let positions = [
+            (healthy.clone(), [user_balance(&token_a, 0, 1000, 500)].to_vec()),
+            (unhealthy.clone(), [user_balance(&token_a, 0, 200, 0), user_balance(&token_b, 0, 100, 250)].to_vec()),
+            (lender.clone(), [user_balance(&token_b, 0, 400, 0)].to_vec()),
+        ];
+        let mut active_users = Vec::new(&t.env);
+        for (user, balances) in positions.iter() {
+            let mut user_balances = Map::new(&t.env);
+            for balance in balances.iter() {
+                user_balances.set(balance.token.clone(), balance.clone());
+            }
+            t.env.storage().persistent().set(&DataKey::UserBalances(user.clone()), &user_balances);
+            active_users.push_back(user.clone());
+        }
+        t.env.storage().persistent().set(&DataKey::ActiveUsersList, &active_users);
+    });
+
+    let report = t.client.admin_risk_report(&t.admin);
+    assert_eq!(
+        dust_events(&t).last().unwrap(),
+        &DustEvent::RiskReportGenerated(1700, 750, 1)
+    );
+    assert_eq!(report.total_collateral_usd, 1700);
+    assert_eq!(report.total_debt_usd, 750);
+    assert_eq!(report.protocol_health_factor, 1700 * 80 / 100 * HEALTH_FACTOR_SCALE / 750);
+    assert_eq!(report.users_below_threshold, 1);
+    assert_eq!(report.largest_single_exposure_usd, 500);
+    assert_eq!(report.most_concentrated_token, token_a);
+}
+
+#[test]
+fn test_collateral_factor_rejects_borrow_blend_would_allow() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    t.client.admin_add_token(&t.admin, &token, &token_config(0), &None);
+
+    // Full value: 1000 * 80% / 700 = 1.14, above the minimum
+    t.pool.set_position(&position(&t.env, &[(token.clone(), 1000)], &[(token.clone(), 700)]));
+    t.client.borrow_against_dust(&user, &token, &10, &0);
+
+    t.client.admin_update_collateral_factor(&t.admin, &token, &7500);
+    assert_eq!(
+        dust_events(&t),
+        std::vec![DustEvent::CollateralFactorUpdated(token.clone(), 10000, 7500)]
+    );
+    assert_eq!(t.client.get_token_config(&token).unwrap().collateral_factor_bps, 7500);
+
+    // At 75%: 750 * 80% / 700 = 0.857
+    assert!(t.client.try_borrow_against_dust(&user, &token, &10, &0).is_err());
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_collateral_factor_rejects_zero() {
+    let t = setup();
+    let token = create_token(&t.env);
+    t.client.admin_add_token(&t.admin, &token, &token_config(0), &None);
+    t.client.admin_update_collateral_factor(&t.admin, &token, &0);
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_collateral_factor_rejects_above_full_value() {
+    let t = setup();
+    let token = create_token(&t.env);
+    t.client.admin_add_token(&t.admin, &token, &token_config(0), &None);
+    t.client.admin_update_collateral_factor(&t.admin, &token, &10001);
+}
+
+#[test]
+fn test_health_factor_aggregates_across_pools() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let retired_id = t.env.register(MockBlendPool, ());
+    let retired = MockBlendPoolClient::new(&t.env, &retired_id);
+    t.env.as_contract(&t.contract_id, || {
+        t.env.storage().instance().set(&DataKey::RetiredPools, &Vec::from_array(&t.env, [retired_id.clone()]));
+    });
+
+    // The user's own supply and debt sit in the active pool; another user's do not count
+    let other = Address::generate(&t.env);
+    t.client.supply_to_blend(&user, &token, &1000, &None);
+    t.client.borrow_against_dust(&user, &token, &400, &0);
+    t.client.supply_to_blend(&other, &token, &5000, &None);
+    assert_eq!(
+        t.client.get_per_pool_health_factor(&user, &t.pool.address),
+        1000 * 8000 * HEALTH_FACTOR_SCALE / 400 / 10000
+    );
+    assert_eq!(t.client.get_per_pool_health_factor(&user, &retired_id), i128::MAX);
+
+    // Healthy in the active pool, underwater in the retired one
+    t.pool.set_position(&position(&t.env, &[(token.clone(), 1000)], &[(token.clone(), 400)]));
+    retired.set_position(&position(&t.env, &[(token.clone(), 100)], &[(token.clone(), 200)]));
+
+    let composite = t.env.as_contract(&t.contract_id, || {
+        DustAggregator::calculate_health_factor(&t.env, &user)
+    });
+    assert_eq!(composite, 1100 * 8000 * HEALTH_FACTOR_SCALE / 600 / 10000);
+}
+
+#[test]
+fn test_import_position_tracks_existing_blend_position() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.pool.set_position(&position(&t.env, &[(token.clone(), 1000)], &[(token.clone(), 300)]));
+    t.client.import_position(&user, &token);
+    assert_eq!(
+        dust_events(&t),
+        std::vec![DustEvent::PositionImported(user.clone(), token.clone(), 1000, 300)]
+    );
+
+    let balance = t.client.get_user_balance(&user, &token);
+    assert!(balance.is_imported);
+    assert_eq!(balance.supplied_to_blend, 1000);
+    assert_eq!(balance.borrowed_from_blend, 300);
+    assert_eq!(t.client.get_stats().2, 1);
+
+    // Supplying is blocked until the user hands control over
+    assert!(t.client.try_supply_to_blend(&user, &token, &100, &None).is_err());
+
+    t.client.claim_position(&user, &token);
+    assert!(!t.client.get_user_balance(&user, &token).is_imported);
+    t.client.supply_to_blend(&user, &token, &100, &None);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 1100);
+}
+
+#[test]
+#[should_panic(expected = "Position already managed")]
+fn test_import_position_rejects_native_position() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &100, &None);
+    t.client.import_position(&user, &token);
+}
+
+#[test]
+fn test_flash_loan_nonce_rejects_duplicates() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let params = arbitrage_params(&t.env, &token, 1_000_000);
+    assert!(!t.client.get_nonce_used(&user, &params.nonce));
+    t.client.flash_loan_arbitrage(&user, &params);
+    assert!(t.client.get_nonce_used(&user, &params.nonce));
+    assert!(t.client.try_flash_loan_arbitrage(&user, &params).is_err());
+
+    // A fresh nonce goes through independently
+    let other = arbitrage_params(&t.env, &token, 1_000_000);
+    t.client.flash_loan_arbitrage(&user, &other);
+    assert!(t.client.get_nonce_used(&user, &other.nonce));
+
+    // Other users are unaffected by this user's nonces
+    let other_user = Address::generate(&t.env);
+    t.client.flash_loan_arbitrage(&other_user, &params);
+}
+
+#[test]
+fn test_flash_loan_nonce_expires() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let params = arbitrage_params(&t.env, &token, 1_000_000);
+    t.client.flash_loan_arbitrage(&user, &params);
+
+    t.env.ledger().with_mut(|li| li.sequence_number += NONCE_EXPIRY_LEDGERS - 1);
+    assert!(t.client.try_flash_loan_arbitrage(&user, &params).is_err());
+
+    t.env.ledger().with_mut(|li| li.sequence_number += 1);
+    t.client.flash_loan_arbitrage(&user, &params);
+}
+
+#[test]
+fn test_auto_yield_supplies_to_highest_rate_pool() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let other_id = t.env.register(MockBlendPool, ());
+    let other = MockBlendPoolClient::new(&t.env, &other_id);
+    t.factory.set_pool(&other_id, &true);
+    t.client.admin_set_yield_pools(&t.admin, &Vec::from_array(&t.env, [other_id.clone()]));
+
+    // 20% utilization in the primary pool, 60% in the other
+    t.pool.set_reserve(&token, &PoolReserve { total_supply: 10_000, total_liabilities: 2_000 });
+    other.set_reserve(&token, &PoolReserve { total_supply: 10_000, total_liabilities: 6_000 });
+    assert_eq!(t.client.get_best_pool_for_token(&token), other_id);
+
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    assert_eq!(t.pool.submitted().len(), 0);
+    let submitted = other.submitted();
+    assert_eq!(submitted.len(), 1);
+    assert_eq!(submitted.get(0).unwrap().amount, 1_000);
+    assert_eq!(t.client.get_user_balance(&user, &token).active_pool, other_id);
+
+    // Withdrawals come back out of the pool that holds the supply
+    t.client.withdraw_from_blend(&user, &token, &400);
+    assert_eq!(other.submitted().len(), 2);
+    assert_eq!(t.pool.submitted().len(), 0);
+
+    // Once the primary pool pays more, new supply goes there
+    t.pool.set_reserve(&token, &PoolReserve { total_supply: 10_000, total_liabilities: 7_000 });
+    t.client.supply_to_blend(&user, &token, &500, &None);
+    assert_eq!(t.pool.submitted().len(), 1);
+    assert_eq!(t.client.get_user_balance(&user, &token).active_pool, t.pool.address);
+}
+
+#[test]
+#[should_panic(expected = "Invalid blend pool")]
+fn test_yield_pools_must_be_factory_pools() {
+    let t = setup();
+    let unknown = t.env.register(MockBlendPool, ());
+    t.client.admin_set_yield_pools(&t.admin, &Vec::from_array(&t.env, [unknown]));
+}
+
+#[test]
+fn test_rebalance_pool_weights_migrates_deltas() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let other_id = t.env.register(MockBlendPool, ());
+    let other = MockBlendPoolClient::new(&t.env, &other_id);
+    t.factory.set_pool(&other_id, &true);
+    let primary_id = t.pool.address.clone();
+
+    // 600 in the primary pool, 400 in the other: already at 60/40
+    t.env.as_contract(&t.contract_id, || {
+        let mut user_balances = Map::new(&t.env);
+        user_balances.set(token.clone(), user_balance(&token, 0, 1000, 0));
+        t.env.storage().persistent().set(&DataKey::UserBalances(user.clone()), &user_balances);
+        t.env.storage().persistent().set(&DataKey::ActiveUsersList, &Vec::from_array(&t.env, [user.clone()]));
+        for (pool, amount) in [(primary_id.clone(), 600i128), (other_id.clone(), 400)] {
+            let mut per_pool_supplied = Map::new(&t.env);
+            per_pool_supplied.set(token.clone(), amount);
+            t.env.storage().persistent().set(&DataKey::PerPoolSupplied(user.clone(), pool), &per_pool_supplied);
+        }
+    });
+
+    t.client.admin_rebalance_pool_weights(&t.admin, &Vec::from_array(&t.env, [
+        (primary_id.clone(), 6000u32),
+        (other_id.clone(), 4000u32),
+    ]));
+    assert_eq!(dust_events(&t).last(), Some(&DustEvent::PoolRebalanced(0, 0, 0)));
+    assert_eq!(t.pool.submitted().len(), 0);
+
+    t.client.admin_rebalance_pool_weights(&t.admin, &Vec::from_array(&t.env, [
+        (primary_id.clone(), 4000u32),
+        (other_id.clone(), 6000u32),
+    ]));
+    assert_eq!(dust_events(&t).last(), Some(&DustEvent::PoolRebalanced(1, 1, 200)));
+
+    let withdrawn = t.pool.submitted().get(0).unwrap();
+    assert_eq!((withdrawn.request_type, withdrawn.amount), (REQUEST_WITHDRAW_COLLATERAL, 200));
+    let deposited = other.submitted().get(0).unwrap();
+    assert_eq!((deposited.request_type, deposited.amount), (REQUEST_DEPOSIT_COLLATERAL, 200));
+
+    let supplied = |pool: &Address| t.env.as_contract(&t.contract_id, || {
+        let per_pool_supplied: Map<Address, i128> = t.env.storage().persistent()
+            .get(&DataKey::PerPoolSupplied(user.clone(), pool.clone()))
+            .unwrap();
+        per_pool_supplied.get(token.clone()).unwrap()
+    });
+    assert_eq!(supplied(&primary_id), 400);
+    assert_eq!(supplied(&other_id), 600);
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_rebalance_pool_weights_must_sum_to_full() {
+    let t = setup();
+    let pool = t.pool.address.clone();
+    t.client.admin_rebalance_pool_weights(&t.admin, &Vec::from_array(&t.env, [(pool, 9000u32)]));
+}
+
+#[test]
+fn test_historical_tvl_hourly_buckets() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.env.ledger().with_mut(|li| li.sequence_number = LEDGERS_PER_HOUR * 10);
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    t.client.supply_to_blend(&user, &token, &500, &None);
+
+    // Two hours later
+    t.env.ledger().with_mut(|li| {
+        li.sequence_number += 2 * LEDGERS_PER_HOUR;
+        li.timestamp += 7200;
+    });
+    t.client.withdraw_from_blend(&user, &token, &300);
+
+    let history = t.client.get_historical_tvl(&3);
+    assert_eq!(history.len(), 2);
+
+    let first = history.get(0).unwrap();
+    assert_eq!((first.hour, first.tvl, first.supply_count), (10, 1_500, 2));
+    let second = history.get(1).unwrap();
+    assert_eq!((second.hour, second.tvl, second.supply_count), (12, 1_200, 0));
+    assert_eq!(second.timestamp, t.env.ledger().timestamp());
+
+    // Only the latest hour falls in a one-bucket window
+    assert_eq!(t.client.get_historical_tvl(&1).len(), 1);
+}
+
+#[test]
+fn test_historical_tvl_ring_buffer_overwrites_week_old_bucket() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    t.env.ledger().with_mut(|li| li.sequence_number += TVL_BUCKET_COUNT * LEDGERS_PER_HOUR);
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+
+    let history = t.client.get_historical_tvl(&TVL_BUCKET_COUNT);
+    assert_eq!(history.len(), 1);
+    assert_eq!(history.get(0).unwrap().supply_count, 1);
+    assert_eq!(history.get(0).unwrap().tvl, 2_000);
+}
+
+#[test]
+fn test_withdrawal_queued_when_pool_lacks_liquidity() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let other_user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    t.client.supply_to_blend(&other_user, &token, &1_000, &None);
+    t.pool.set_reject_withdrawals(&true);
+
+    t.client.withdraw_from_blend(&user, &token, &400);
+    assert_eq!(
+        dust_events(&t).last(),
+        Some(&DustEvent::WithdrawalQueued(user.clone(), token.clone(), 400))
+    );
+    t.client.withdraw_from_blend(&other_user, &token, &100);
+
+    // Nothing moved while the pool is short
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 1_000);
+    let pending = t.client.get_pending_withdrawals(&user);
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending.get(0).unwrap().amount, 400);
+    assert_eq!(pending.get(0).unwrap().requested_at, t.env.ledger().sequence());
+
+    assert_eq!(t.client.process_withdrawal_queue(&token, &10), 0);
+    assert_eq!(
+        dust_events(&t),
+        std::vec![
+            DustEvent::WithdrawalStillPending(user.clone(), token.clone(), 400),
+            DustEvent::WithdrawalStillPending(other_user.clone(), token.clone(), 100),
+        ]
+    );
+
+    // Liquidity returns: only the oldest request fits in this batch
+    t.pool.set_reject_withdrawals(&false);
+    assert_eq!(t.client.process_withdrawal_queue(&token, &1), 1);
+    assert_eq!(
+        dust_events(&t).last(),
+        Some(&DustEvent::WithdrawalProcessed(user.clone(), token.clone(), 400))
+    );
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 600);
+    assert_eq!(t.client.get_pending_withdrawals(&user).len(), 0);
+    assert_eq!(t.client.get_pending_withdrawals(&other_user).len(), 1);
+
+    assert_eq!(t.client.process_withdrawal_queue(&token, &10), 1);
+    assert_eq!(t.client.get_user_balance(&other_user, &token).supplied_to_blend, 900);
+}
+
+#[test]
+fn test_withdrawal_queue_bounded_by_supply_and_accrues_only_on_success() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1_000_000, &None);
+    set_supply_rate(&t, &token, 1000);
+    t.pool.set_reject_withdrawals(&true);
+
+    // A rejected withdrawal leaves the accrual checkpoint alone
+    t.env.ledger().with_mut(|li| li.timestamp += SECONDS_PER_YEAR as u64 / 2);
+    t.client.withdraw_from_blend(&user, &token, &600_000);
+    assert_eq!(t.client.get_user_balance(&user, &token).accrued_interest, 0);
+
+    // Queued requests together cannot exceed what the user supplied
+    assert!(t.client.try_withdraw_from_blend(&user, &token, &400_001).is_err());
+    t.client.withdraw_from_blend(&user, &token, &400_000);
+    assert_eq!(t.client.get_pending_withdrawals(&user).len(), 2);
+
+    // Once the pool pays out, interest accrues on the full supply held until then
+    t.pool.set_reject_withdrawals(&false);
+    assert_eq!(t.client.process_withdrawal_queue(&token, &1), 1);
+    let balance = t.client.get_user_balance(&user, &token);
+    assert_eq!(balance.supplied_to_blend, 400_000);
+    assert_eq!(balance.accrued_interest, 50_000);
+}
+
+#[test]
+fn test_withdrawal_queue_shrinks_and_drops_stale_requests() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    t.pool.set_reject_withdrawals(&true);
+    t.client.withdraw_from_blend(&user, &token, &600);
+    t.client.withdraw_from_blend(&user, &token, &400);
+
+    // The position loses supply while the requests wait, e.g. to a liquidation
+    t.env.as_contract(&t.contract_id, || {
+        let mut balances: Map<Address, UserBalance> = t.env.storage().persistent()
+            .get(&DataKey::UserBalances(user.clone()))
+            .unwrap();
+        let mut balance = balances.get(token.clone()).unwrap();
+        balance.supplied_to_blend = 500;
+        balances.set(token.clone(), balance);
+        t.env.storage().persistent().set(&DataKey::UserBalances(user.clone()), &balances);
+    });
+
+    // The first request is cut to what is left and the second has nothing left to pay
+    t.pool.set_reject_withdrawals(&false);
+    assert_eq!(t.client.process_withdrawal_queue(&token, &10), 1);
+    assert_eq!(
+        dust_events(&t),
+        std::vec![DustEvent::WithdrawalProcessed(user.clone(), token.clone(), 500)]
+    );
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 0);
+    assert_eq!(t.client.get_pending_withdrawals(&user).len(), 0);
+    assert_eq!(t.pool.submitted().last().unwrap().amount, 500);
+}
+
+#[test]
+fn test_borrow_respects_caller_health_factor_floor() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    // 1000 collateral against 400 debt; 100 more leaves 800 / 500 = 1.6
+    t.pool.set_position(&position(&t.env, &[(token.clone(), 1000)], &[(token.clone(), 400)]));
+
+    let floor = HEALTH_FACTOR_SCALE * 17 / 10;
+    assert!(t.client.try_borrow_against_dust(&user, &token, &100, &floor).is_err());
+    assert_eq!(t.pool.submitted().len(), 0);
+
+    t.client.borrow_against_dust(&user, &token, &100, &(HEALTH_FACTOR_SCALE * 16 / 10));
+    assert_eq!(t.client.get_user_balance(&user, &token).borrowed_from_blend, 100);
+}
+
+#[test]
+fn test_interaction_stats_track_each_operation() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    assert_eq!(t.client.get_user_interaction_stats(&user, &token), (Symbol::new(&t.env, ""), 0));
+
+    set_supply_rate(&t, &token, 1000);
+    t.client.supply_to_blend(&user, &token, &1_000_000, &None);
+    assert_eq!(t.client.get_user_interaction_stats(&user, &token), (Symbol::new(&t.env, "supply"), 1));
+
+    t.client.borrow_against_dust(&user, &token, &100, &0);
+    assert_eq!(t.client.get_user_interaction_stats(&user, &token), (Symbol::new(&t.env, "borrow"), 2));
+
+    t.client.repay_blend_debt(&user, &token, &100);
+    assert_eq!(t.client.get_user_interaction_stats(&user, &token), (Symbol::new(&t.env, "repay"), 3));
+
+    t.env.ledger().with_mut(|li| li.timestamp += (SECONDS_PER_YEAR / 2) as u64);
+    t.client.auto_compound(&user, &token);
+    assert_eq!(t.client.get_user_interaction_stats(&user, &token), (Symbol::new(&t.env, "compound"), 4));
+
+    t.client.withdraw_from_blend(&user, &token, &1_000);
+    assert_eq!(t.client.get_user_interaction_stats(&user, &token), (Symbol::new(&t.env, "withdraw"), 5));
+
+    assert_eq!(t.client.get_global_interaction_count(), 5);
+}
+
+#[test]
+fn test_global_interaction_count_once_per_call() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token_a = create_token(&t.env);
+    let token_b = create_token(&t.env);
+
+    t.client.borrow_against_dust(&user, &token_a, &500, &0);
+    t.client.borrow_against_dust(&user, &token_b, &300, &0);
+    assert_eq!(t.client.get_global_interaction_count(), 2);
+
+    // Repaying two tokens in one call is still a single interaction
+    let tokens = Vec::from_array(&t.env, [token_a.clone(), token_b.clone()]);
+    let amounts = Vec::from_array(&t.env, [500i128, 100i128]);
+    t.client.batch_repay(&user, &tokens, &amounts);
+    assert_eq!(t.client.get_global_interaction_count(), 3);
+
+    assert_eq!(t.client.get_user_interaction_stats(&user, &token_a), (Symbol::new(&t.env, "repay"), 2));
+    assert_eq!(t.client.get_user_interaction_stats(&user, &token_b), (Symbol::new(&t.env, "repay"), 2));
+}
+
+#[test]
+fn test_flash_loan_fee_waived_in_grace_period() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    assert_eq!(t.client.get_user_balance(&user, &token).first_supply_ledger, t.env.ledger().sequence());
+
+    // 15_000 profit per trade, kept in full inside the window
+    t.env.ledger().with_mut(|li| li.sequence_number += DEFAULT_GRACE_PERIOD_LEDGERS - 1);
+    let fee = 15_000 * t.client.get_tier_fee_rate(&user) / 10000;
+    assert_eq!(t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000)), 15_000);
+    assert!(dust_events(&t).contains(&DustEvent::GracePeriodFeeWaived(user.clone(), fee)));
+
+    t.env.ledger().with_mut(|li| li.sequence_number += 1);
+    let fee = 15_000 * t.client.get_tier_fee_rate(&user) / 10000;
+    assert!(fee > 0);
+    assert_eq!(t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000)), 15_000 - fee);
+}
+
+#[test]
+fn test_grace_period_is_configurable() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    // No supply yet, so the standard fee applies
+    assert_eq!(t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000)), 14_850);
+
+    t.client.admin_set_grace_period(&t.admin, &0);
+    assert_eq!(read_config(&t).grace_period_ledgers, 0);
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    assert!(t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000)) < 15_000);
+}
+
+#[test]
+fn test_multi_token_arbitrage_single_flash_loan() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token_a = create_token(&t.env);
+    let token_b = create_token(&t.env);
+
+    let submitted_before = t.pool.submitted().len();
+    let params_list = Vec::from_array(&t.env, [
+        arbitrage_params(&t.env, &token_a, 1_000_000),
+        arbitrage_params(&t.env, &token_b, 2_000_000),
+    ]);
+    let profits = t.client.multi_token_arbitrage(&user, &params_list);
+    let executed: std::vec::Vec<DustEvent> = dust_events(&t).into_iter()
+        .filter(|event| matches!(event, DustEvent::FlashLoanExecuted(..)))
+        .collect();
+    assert_eq!(executed.len(), 2);
+
+    // 1.5% gross per leg less the tier fee
+    let fee_rate = 100;
+    assert_eq!(profits, Vec::from_array(&t.env, [15_000 - 15_000 * fee_rate / 10000, 30_000 - 30_000 * fee_rate / 10000]));
+
+    // Both borrows, then both repays, in one flash loan
+    let submitted = t.pool.submitted();
+    assert_eq!(submitted.len(), submitted_before + 4);
+    let types: std::vec::Vec<u32> = (submitted_before..submitted.len())
+        .map(|i| submitted.get(i).unwrap().request_type)
+        .collect();
+    assert_eq!(types, std::vec![REQUEST_BORROW, REQUEST_BORROW, REQUEST_REPAY, REQUEST_REPAY]);
+
+    assert!(t.client.get_nonce_used(&user, &params_list.get(1).unwrap().nonce));
+}
+
+#[test]
+fn test_multi_token_arbitrage_rejects_oversized_batch() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let mut params_list = Vec::new(&t.env);
+    for _ in 0..MAX_ARBITRAGE_BATCH + 1 {
+        params_list.push_back(arbitrage_params(&t.env, &token, 1_000));
+    }
+    assert!(t.client.try_multi_token_arbitrage(&user, &params_list).is_err());
+    assert!(t.client.try_multi_token_arbitrage(&user, &Vec::new(&t.env)).is_err());
+}
+
+#[test]
+#[should_panic(expected = "Duplicate nonce")]
+fn test_multi_token_arbitrage_rejects_repeated_nonce() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let params = arbitrage_params(&t.env, &token, 1_000_000);
+    t.client.multi_token_arbitrage(&user, &Vec::from_array(&t.env, [params.clone(), params]));
+}
+
+#[test]
+fn test_auto_deleverage_to_target_lands_on_target() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+    let router = setup_router(&t, 10000);
+
+    t.client.supply_to_blend(&user, &collateral, &1_000_000, &None);
+    t.client.borrow_against_dust(&user, &debt, &900_000, &0);
+    mint(&t.env, &collateral, &t.pool.address, 1_000_000);
+    mint(&t.env, &debt, &router.address, 700_000);
+    t.pool.set_move_tokens(&true);
+
+    let target = HEALTH_FACTOR_SCALE * 12 / 10;
+    let new_health_factor = t.client.auto_deleverage_to_target(&user, &collateral, &debt, &target);
+    assert!((new_health_factor - target).abs() * 100 <= target);
+    assert!(dust_events(&t).contains(&DustEvent::DeleveragedToTarget(user.clone(), 700_000, 700_000, new_health_factor)));
+
+    assert_eq!(t.client.get_user_balance(&user, &collateral).supplied_to_blend, 300_000);
+    assert_eq!(t.client.get_user_balance(&user, &debt).borrowed_from_blend, 200_000);
+
+    // The sold collateral went through the router and its output repaid the pool
+    let collateral_token = TokenClient::new(&t.env, &collateral);
+    let debt_token = TokenClient::new(&t.env, &debt);
+    assert_eq!(collateral_token.balance(&router.address), 700_000);
+    assert_eq!(debt_token.balance(&t.pool.address), 700_000);
+    assert_eq!(collateral_token.balance(&t.contract_id), 0);
+    assert_eq!(debt_token.balance(&t.contract_id), 0);
+}
+
+#[test]
+#[should_panic(expected = "insufficient output amount")]
+fn test_auto_deleverage_to_target_rejects_swap_below_oracle_value() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+    // The router pays 10% less than the oracle says the collateral is worth
+    let router = setup_router(&t, 9000);
+
+    t.client.supply_to_blend(&user, &collateral, &1_000_000, &None);
+    t.client.borrow_against_dust(&user, &debt, &900_000, &0);
+    mint(&t.env, &collateral, &t.pool.address, 1_000_000);
+    mint(&t.env, &debt, &router.address, 700_000);
+    t.pool.set_move_tokens(&true);
+
+    t.client.auto_deleverage_to_target(&user, &collateral, &debt, &(HEALTH_FACTOR_SCALE * 12 / 10));
+}
+
+#[test]
+#[should_panic(expected = "Health factor too low")]
+fn test_auto_deleverage_to_target_rejects_target_below_minimum() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &collateral, &1_000_000, &None);
+    t.client.borrow_against_dust(&user, &debt, &900_000, &0);
+    t.client.auto_deleverage_to_target(&user, &collateral, &debt, &(HEALTH_FACTOR_SCALE - 1));
+}
+
+fn scored_params(t: &TestSetup, loan_token: &Address, hops: u32) -> ArbitrageParams {
+    let mut params = arbitrage_params(&t.env, loan_token, 1_000_000);
+    params.swap_path.push_back(loan_token.clone());
+    for _ in 1..hops {
+        params.swap_path.push_back(create_token(&t.env));
+    }
+    params.swap_path.push_back(loan_token.clone());
+    params
+}
+
+#[test]
+fn test_opportunity_score_prefers_shorter_paths() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let router = setup_router(&t, 10000);
+
+    t.client.supply_to_blend(&user, &token, &500_000, &None);
+
+    // Both paths return the same 2% profit
+    router.set_path_output(&1, &1_020_000);
+    router.set_path_output(&3, &1_020_000);
+    let short = t.client.get_arbitrage_opportunity_score(&scored_params(&t, &token, 1));
+    let long = t.client.get_arbitrage_opportunity_score(&scored_params(&t, &token, 3));
+
+    assert_eq!(short.estimated_profit, 20_000);
+    assert_eq!(long.estimated_profit, 20_000);
+    assert_eq!(short.risk_score, 9000);
+    assert_eq!(long.risk_score, 7000);
+    assert_eq!(short.liquidity_score, 5000);
+    assert_eq!(long.liquidity_score, 5000);
+    assert!(short.composite_score > long.composite_score);
+
+    // Tolerating more slippage costs the same path some of its score
+    let mut loose = scored_params(&t, &token, 1);
+    loose.slippage_bps = 500;
+    let loose = t.client.get_arbitrage_opportunity_score(&loose);
+    assert_eq!(loose.risk_score, 8500);
+    assert!(loose.composite_score < short.composite_score);
+}
+
+#[test]
+fn test_opportunity_score_zero_for_unprofitable_path() {
+    let t = setup();
+    let token = create_token(&t.env);
+    setup_router(&t, 9900);
+
+    let score = t.client.get_arbitrage_opportunity_score(&scored_params(&t, &token, 1));
+    assert_eq!(score.estimated_profit, -10_000);
+    assert_eq!(score.composite_score, 0);
+}
+
+#[test]
+fn test_flash_loan_fee_sent_to_recipient_unless_deferred() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let treasury = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    assert_eq!(t.client.get_fee_recipient(), t.admin);
+    t.client.admin_set_fee_recipient(&t.admin, &treasury);
+    t.client.admin_set_defer_fee_transfer(&t.admin, &false);
+    assert!(!read_config(&t).defer_fee_transfer);
+
+    // The arbitrage profit lands on the contract; 1% of 15_000 goes straight to the treasury
+    mint(&t.env, &token, &t.contract_id, 15_000);
+    t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000));
+    assert!(dust_events(&t).contains(&DustEvent::FeeTransferred(treasury.clone(), token.clone(), 150)));
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&treasury), 150);
+    assert_eq!(t.client.get_collected_fees(&token), 0);
+
+    // Deferred fees stay on the contract until withdrawn
+    t.client.admin_set_defer_fee_transfer(&t.admin, &true);
+    let fee = 15_000 * t.client.get_tier_fee_rate(&user) / 10000;
+    t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000));
+    assert_eq!(t.client.get_collected_fees(&token), fee);
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&treasury), 150);
+}
+
+#[test]
+fn test_flash_loan_warns_when_swap_output_trails_quote() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let router = setup_router(&t, 10000);
+
+    // Quoted at 1_020_000; the swaps return 1_015_000, within 5% of the quote
+    router.set_path_output(&1, &1_020_000);
+    t.client.flash_loan_arbitrage(&user, &scored_params(&t, &token, 1));
+    assert!(!dust_events(&t).iter().any(|event| matches!(event, DustEvent::SlippageWarning(..))));
+
+    // Quoted at 1_100_000 on a later ledger, the same swaps fall more than 5% short
+    router.set_path_output(&1, &1_100_000);
+    t.env.ledger().with_mut(|li| li.sequence_number += 1);
+    t.client.flash_loan_arbitrage(&user, &scored_params(&t, &token, 1));
+    assert!(dust_events(&t).contains(&DustEvent::SlippageWarning(user.clone(), token.clone(), 1_100_000, 1_015_000)));
+}
+
+#[test]
+fn test_flash_loan_rejects_unprofitable_quote_before_borrowing() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let router = setup_router(&t, 10000);
+
+    // 2% quoted profit is wiped out by a 3% slippage allowance
+    router.set_path_output(&1, &1_020_000);
+    let mut params = scored_params(&t, &token, 1);
+    params.slippage_bps = 300;
+
+    let submitted_before = t.pool.submitted().len();
+    let result = t.client.try_flash_loan_arbitrage(&user, &params);
+    assert!(result.is_err());
+    assert_eq!(t.pool.submitted().len(), submitted_before);
+
+    params.slippage_bps = 50;
+    t.client.flash_loan_arbitrage(&user, &params);
+    assert_eq!(t.pool.submitted().len(), submitted_before + 2);
+}
+
+fn accrue_half_year_yield(t: &TestSetup, user: &Address, token: &Address) {
+    // 10% APY on 1_000_000 for half a year accrues 50_000
+    set_supply_rate(t, token, 1000);
+    t.client.supply_to_blend(user, token, &1_000_000, &None);
+    t.env.ledger().with_mut(|li| li.timestamp += (SECONDS_PER_YEAR / 2) as u64);
+}
+
+#[test]
+fn test_compounding_strategy_resupply_by_default() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    accrue_half_year_yield(&t, &user, &token);
+    assert_eq!(t.client.get_compounding_strategy(&user, &token), CompoundingStrategy::ReSupply);
+    assert_eq!(t.client.auto_compound(&user, &token), 50_000);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 1_050_000);
+}
+
+#[test]
+fn test_compounding_strategy_convert_to_stable() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let stable = create_token(&t.env);
+    let router = setup_router(&t, 20000);
+
+    accrue_half_year_yield(&t, &user, &token);
+    mint(&t.env, &token, &t.pool.address, 50_000);
+    mint(&t.env, &stable, &router.address, 100_000);
+    t.pool.set_move_tokens(&true);
+    t.client.set_compounding_strategy(&user, &token, &CompoundingStrategy::ConvertToStable(stable.clone()));
+    assert_eq!(t.client.auto_compound(&user, &token), 50_000);
+
+    // The yield leaves the token position and is supplied as the stable at the router's 2x rate
+    let balance = t.client.get_user_balance(&user, &token);
+    assert_eq!(balance.supplied_to_blend, 1_000_000);
+    assert_eq!(balance.accrued_interest, 0);
+    assert_eq!(t.client.get_user_balance(&user, &stable).supplied_to_blend, 100_000);
+
+    let submitted = t.pool.submitted();
+    let withdraw = submitted.get(submitted.len() - 2).unwrap();
+    assert_eq!((withdraw.request_type, withdraw.address, withdraw.amount), (REQUEST_WITHDRAW_COLLATERAL, token.clone(), 50_000));
+    let supply = submitted.last().unwrap();
+    assert_eq!((supply.request_type, supply.address, supply.amount), (REQUEST_DEPOSIT_COLLATERAL, stable.clone(), 100_000));
+
+    // Real tokens moved: the yield went to the router and the stable it returned went to the pool
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&router.address), 50_000);
+    assert_eq!(TokenClient::new(&t.env, &stable).balance(&router.address), 0);
+    assert_eq!(TokenClient::new(&t.env, &stable).balance(&t.pool.address), 100_000);
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&t.contract_id), 0);
+    assert_eq!(TokenClient::new(&t.env, &stable).balance(&t.contract_id), 0);
+}
+
+#[test]
+#[should_panic(expected = "insufficient output amount")]
+fn test_compounding_strategy_convert_to_stable_rejects_swap_below_oracle_value() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let stable = create_token(&t.env);
+    // Both tokens are priced at $1, so a 10% haircut is past the allowed slippage
+    let router = setup_router(&t, 9000);
+
+    accrue_half_year_yield(&t, &user, &token);
+    mint(&t.env, &token, &t.pool.address, 50_000);
+    mint(&t.env, &stable, &router.address, 50_000);
+    t.pool.set_move_tokens(&true);
+    t.client.set_compounding_strategy(&user, &token, &CompoundingStrategy::ConvertToStable(stable));
+    t.client.auto_compound(&user, &token);
+}
+
+#[test]
+fn test_compounding_strategy_withdraw_pays_user() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    accrue_half_year_yield(&t, &user, &token);
+    mint(&t.env, &token, &t.contract_id, 50_000);
+    t.client.set_compounding_strategy(&user, &token, &CompoundingStrategy::Withdraw);
+    assert_eq!(t.client.auto_compound(&user, &token), 50_000);
+
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 1_000_000);
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&user), 50_000);
+}
+
+#[test]
+fn test_pool_utilization_snapshot_pays_keeper_with_cooldown() {
+    let t = setup();
+    let keeper = Address::generate(&t.env);
+    let funder = Address::generate(&t.env);
+    let stablecoin = create_token(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.admin_set_keeper_reward(&t.admin, &KeeperReward { token: stablecoin.clone(), amount: 25 });
+    mint(&t.env, &stablecoin, &funder, 40);
+    t.client.fund_keeper_reward_pool(&funder, &40);
+    assert_eq!(t.client.get_keeper_reward_pool(), 40);
+
+    t.pool.set_reserves(&PoolReserve { total_supply: 1_000_000, total_liabilities: 500_000 });
+    let snapshot = t.client.record_pool_utilization(&keeper, &t.pool.address);
+    assert_eq!(snapshot.total_supply, 1_000_000);
+    assert_eq!(snapshot.total_borrows, 500_000);
+    assert_eq!(t.client.get_pool_utilization(&t.pool.address), Some(snapshot.clone()));
+    let stored: PoolUtilizationSnapshot = t.env.as_contract(&t.contract_id, || {
+        t.env.storage().persistent()
+            .get(&UtilizationKey::PoolUtilizationSnapshot(t.pool.address.clone(), t.env.ledger().sequence()))
+            .unwrap()
+    });
+    assert_eq!(stored, snapshot);
+    assert_eq!(TokenClient::new(&t.env, &stablecoin).balance(&keeper), 25);
+
+    // Recorded 50% utilization now drives the rate model
+    assert_eq!(t.client.compute_borrow_rate(&token), 250);
+
+    t.env.ledger().with_mut(|li| li.sequence_number += UTILIZATION_SNAPSHOT_INTERVAL_LEDGERS - 1);
+    assert!(t.client.try_record_pool_utilization(&keeper, &t.pool.address).is_err());
+
+    // Only 15 is left in the reward pool
+    t.env.ledger().with_mut(|li| li.sequence_number += 1);
+    t.client.record_pool_utilization(&keeper, &t.pool.address);
+    assert_eq!(TokenClient::new(&t.env, &stablecoin).balance(&keeper), 40);
+    assert_eq!(t.client.get_keeper_reward_pool(), 0);
+}
+
+#[test]
+#[should_panic(expected = "Invalid blend pool")]
+fn test_pool_utilization_snapshot_rejects_unknown_pool() {
+    let t = setup();
+    t.client.record_pool_utilization(&Address::generate(&t.env), &Address::generate(&t.env));
+}
+
+#[test]
+fn test_claim_blend_rewards_pays_user_and_tracks_total() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let blnd = create_token(&t.env);
+
+    // Nothing to claim is not an error
+    assert_eq!(t.client.claim_blend_rewards(&user, &blnd), 0);
+
+    t.pool.set_rewards(&blnd, &700);
+    mint(&t.env, &blnd, &t.contract_id, 700);
+    assert_eq!(t.client.claim_blend_rewards(&user, &blnd), 700);
+    assert_eq!(dust_events(&t), std::vec![DustEvent::BlendRewardsClaimed(user.clone(), blnd.clone(), 700)]);
+    assert_eq!(TokenClient::new(&t.env, &blnd).balance(&user), 700);
+
+    t.pool.set_rewards(&blnd, &300);
+    mint(&t.env, &blnd, &t.contract_id, 300);
+    t.client.claim_blend_rewards(&user, &blnd);
+    assert_eq!(t.client.get_claimed_rewards(&user, &blnd), 1_000);
+}
+
+#[test]
+fn test_deposit_streak_over_multi_day_pattern() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let deposit_after = |seconds: u64| {
+        t.env.ledger().with_mut(|li| li.timestamp += seconds);
+        t.client.supply_to_blend(&user, &token, &100, &None);
+    };
+
+    deposit_after(0);
+    assert_eq!(t.client.get_deposit_streak(&user).current_streak_days, 1);
+
+    // A second deposit on the same day leaves the streak as is
+    deposit_after(3_600);
+    assert_eq!(t.client.get_deposit_streak(&user).current_streak_days, 1);
+
+    for _ in 0..5 {
+        deposit_after(SECONDS_PER_DAY);
+    }
+    assert_eq!(t.client.get_deposit_streak(&user).current_streak_days, 6);
+
+    deposit_after(SECONDS_PER_DAY);
+    assert!(dust_events(&t).contains(&DustEvent::StreakMilestone(user.clone(), 7)));
+
+    // Skipping a day restarts the streak but keeps the record
+    deposit_after(SECONDS_PER_DAY * 2);
+    let streak = t.client.get_deposit_streak(&user);
+    assert_eq!(streak.current_streak_days, 1);
+    assert_eq!(streak.longest_streak, 7);
+    assert_eq!(streak.last_deposit_timestamp, t.env.ledger().timestamp());
+}
+
+#[test]
+fn test_authorized_wasm_hash_checked_against_installed() {
+    let t = setup();
+    let wasm_hash = BytesN::from_array(&t.env, &[7; 32]);
+    let version = ContractVersion { major: 1, minor: 1, patch: 0, deployed_at: 0 };
+
+    // Nothing authorized yet, so there is no upgrade on record to vouch for
+    assert!(!t.client.verify_contract_integrity());
+    assert!(t.client.try_admin_record_upgrade(&t.admin, &version).is_err());
+
+    t.client.admin_authorize_upgrade(&t.admin, &wasm_hash);
+    assert_eq!(dust_events(&t), std::vec![DustEvent::UpgradeAuthorized(wasm_hash.clone())]);
+    assert_eq!(
+        t.client.get_upgrade_history(),
+        Vec::from_array(&t.env, [(wasm_hash.clone(), t.env.ledger().sequence())])
+    );
+
+    // Authorized but not installed: post-upgrade bookkeeping is refused
+    assert!(!t.client.verify_contract_integrity());
+    assert!(t.client.try_admin_record_upgrade(&t.admin, &version).is_err());
+    assert!(t.client.try_admin_upgrade(&t.admin, &BytesN::from_array(&t.env, &[8; 32])).is_err());
+
+    t.env.as_contract(&t.contract_id, || {
+        t.env.storage().instance().set(&UpgradeKey::InstalledWasmHash, &wasm_hash);
+    });
+    assert!(t.client.verify_contract_integrity());
+    t.client.admin_record_upgrade(&t.admin, &version);
+}
+
+#[test]
+fn test_reinitialize_after_upgrade_requires_integrity() {
+    let t = setup();
+    let pool = t.pool.address.clone();
+    let wasm_hash = BytesN::from_array(&t.env, &[7; 32]);
+
+    // Without an upgrade the contract cannot be initialized twice
+    assert!(t.client.try_initialize(&t.admin, &200, &pool, &HEALTH_FACTOR_SCALE).is_err());
+
+    // What admin_upgrade records when it installs the authorized hash (the native test
+    // contract cannot actually swap its WASM)
+    t.client.admin_authorize_upgrade(&t.admin, &wasm_hash);
+    t.env.as_contract(&t.contract_id, || {
+        t.env.storage().instance().set(&UpgradeKey::InstalledWasmHash, &wasm_hash);
+        t.env.storage().instance().set(&UpgradeKey::ReinitPending, &true);
+    });
+
+    // A different hash authorized since the install fails the integrity check
+    t.client.admin_authorize_upgrade(&t.admin, &BytesN::from_array(&t.env, &[8; 32]));
+    assert!(t.client.try_initialize(&t.admin, &200, &pool, &HEALTH_FACTOR_SCALE).is_err());
+
+    // Only the admin may re-init
+    t.client.admin_authorize_upgrade(&t.admin, &wasm_hash);
+    assert!(t.client.try_initialize(&Address::generate(&t.env), &200, &pool, &HEALTH_FACTOR_SCALE).is_err());
+
+    t.client.initialize(&t.admin, &200, &pool, &(HEALTH_FACTOR_SCALE * 11 / 10));
+    assert_eq!(t.client.get_config().fee_rate, 200);
+    assert_eq!(t.client.get_blend_config().min_health_factor, HEALTH_FACTOR_SCALE * 11 / 10);
+
+    // The re-init window closes once used
+    assert!(t.client.try_initialize(&t.admin, &300, &pool, &HEALTH_FACTOR_SCALE).is_err());
+}
+
+#[test]
+fn test_upgrade_history_drops_oldest() {
+    let t = setup();
+    for i in 0..=UPGRADE_HISTORY_CAPACITY {
+        t.client.admin_authorize_upgrade(&t.admin, &BytesN::from_array(&t.env, &[i as u8; 32]));
+    }
+
+    let history = t.client.get_upgrade_history();
+    assert_eq!(history.len(), UPGRADE_HISTORY_CAPACITY);
+    assert_eq!(history.get(0).unwrap().0, BytesN::from_array(&t.env, &[1; 32]));
+}
+
+#[test]
+fn test_max_withdrawable_at_health_factor_points() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+    t.pool.set_reserve(&collateral, &PoolReserve { total_supply: 10_000_000, total_liabilities: 0 });
+
+    t.client.supply_to_blend(&user, &collateral, &1_000_000, &None);
+    assert_eq!(t.client.get_max_withdrawable(&user, &collateral), 1_000_000);
+
+    // HF 2.0 against a 1.0 minimum: half the collateral is free
+    t.client.borrow_against_dust(&user, &debt, &400_000, &0);
+    assert_eq!(t.client.get_max_withdrawable(&user, &collateral), 500_000);
+
+    // HF 1.25: only a fifth is free
+    t.client.borrow_against_dust(&user, &debt, &240_000, &0);
+    assert_eq!(t.client.get_max_withdrawable(&user, &collateral), 200_000);
+
+    // HF exactly at the minimum leaves nothing
+    t.client.borrow_against_dust(&user, &debt, &160_000, &0);
+    assert_eq!(t.client.get_max_withdrawable(&user, &collateral), 0);
+}
+
+#[test]
+fn test_max_withdrawable_capped_by_pool_liquidity() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1_000_000, &None);
+    t.pool.set_reserve(&token, &PoolReserve { total_supply: 1_000_000, total_liabilities: 900_000 });
+    assert_eq!(t.client.get_max_withdrawable(&user, &token), 100_000);
+}
+
+#[test]
+fn test_max_borrowable_keeps_safety_buffer() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    assert_eq!(t.client.get_max_borrowable(&user, &debt), 0);
+
+    // 1_000_000 collateral at 80% supports 727_272 of debt at HF 1.1
+    t.client.supply_to_blend(&user, &collateral, &1_000_000, &None);
+    let max = t.client.get_max_borrowable(&user, &debt);
+    assert_eq!(max, 727_272);
+
+    // Existing debt is counted against the headroom
+    t.client.borrow_against_dust(&user, &debt, &227_272, &0);
+    assert_eq!(t.client.get_max_borrowable(&user, &debt), 500_000);
+
+    t.client.borrow_against_dust(&user, &debt, &500_000, &0);
+    let health_factor = t.env.as_contract(&t.contract_id, || DustAggregator::calculate_user_health_factor(&t.env, &user));
+    assert!(health_factor >= HEALTH_FACTOR_SCALE * MAX_BORROW_SAFETY_BUFFER_PCT / 100);
+    assert_eq!(t.client.get_max_borrowable(&user, &debt), 0);
+}
+
+#[test]
+fn test_max_borrowable_respects_token_borrow_cap() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.admin_add_token(&t.admin, &debt, &TokenConfig { borrow_cap: 300_000, ..token_config(0) }, &None);
+    t.client.supply_to_blend(&user, &collateral, &1_000_000, &None);
+    assert_eq!(t.client.get_max_borrowable(&user, &debt), 300_000);
+
+    t.client.borrow_against_dust(&user, &debt, &100_000, &0);
+    assert_eq!(t.client.get_max_borrowable(&user, &debt), 200_000);
+    assert!(t.client.try_borrow_against_dust(&user, &debt, &200_001, &0).is_err());
+}
+
+#[test]
+fn test_fee_rebate_for_reward_token_holders() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let reward_token = create_token(&t.env);
+
+    // Half off the fee for holders of 1_000 reward tokens
+    t.client.admin_configure_rebate(&t.admin, &reward_token, &1_000, &5000);
+
+    mint(&t.env, &reward_token, &user, 999);
+    let fee = 15_000 * t.client.get_tier_fee_rate(&user) / 10000;
+    assert_eq!(t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000)), 15_000 - fee);
+    assert!(!dust_events(&t).iter().any(|event| matches!(event, DustEvent::FeeRebateApplied(..))));
+
+    mint(&t.env, &reward_token, &user, 1);
+    let fee = 15_000 * t.client.get_tier_fee_rate(&user) / 10000;
+    assert_eq!(t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000)), 15_000 - fee / 2);
+    assert!(dust_events(&t).contains(&DustEvent::FeeRebateApplied(user.clone(), fee, fee / 2, reward_token.clone())));
+}
+
+#[test]
+fn test_position_lock_blocks_withdrawals_until_unlock_ledger() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let unlock_at = t.env.ledger().sequence() + 1_000;
+
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+    t.client.lock_position(&user, &token, &unlock_at);
+    assert_eq!(t.client.get_position_lock_status(&user, &token), Some(unlock_at));
+
+    // Neither withdrawing nor unlocking early is allowed, nor shortening the lock
+    assert!(t.client.try_withdraw_from_blend(&user, &token, &100).is_err());
+    assert!(t.client.try_unlock_position(&user, &token).is_err());
+    assert!(t.client.try_lock_position(&user, &token, &(unlock_at - 1)).is_err());
+
+    t.env.ledger().with_mut(|li| li.sequence_number = unlock_at);
+    t.client.withdraw_from_blend(&user, &token, &100);
+    t.client.unlock_position(&user, &token);
+    assert_eq!(t.client.get_position_lock_status(&user, &token), None);
+}
+
+#[test]
+fn test_swap_collateral_moves_position_at_router_rate() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let xlm = create_token(&t.env);
+    let usdc = create_token(&t.env);
+    let router = setup_router(&t, 12000);
+
+    t.client.supply_to_blend(&user, &xlm, &1_000_000, &None);
+    t.client.borrow_against_dust(&user, &usdc, &100_000, &0);
+
+    // Asking for more than the router returns is rejected
+    assert!(t.client.try_swap_collateral(&user, &xlm, &usdc, &400_000, &480_001).is_err());
+
+    // The pool holds the supplied XLM and the router the USDC it sells; the contract holds nothing
+    mint(&t.env, &xlm, &t.pool.address, 1_000_000);
+    mint(&t.env, &usdc, &router.address, 480_000);
+    t.pool.set_move_tokens(&true);
+
+    assert_eq!(t.client.swap_collateral(&user, &xlm, &usdc, &400_000, &480_000), 480_000);
+    assert!(dust_events(&t).contains(&DustEvent::CollateralSwapped(user.clone(), xlm.clone(), usdc.clone(), 400_000, 480_000)));
+    assert_eq!(t.client.get_user_balance(&user, &xlm).supplied_to_blend, 600_000);
+    let usdc_balance = t.client.get_user_balance(&user, &usdc);
+    assert_eq!(usdc_balance.supplied_to_blend, 480_000);
+    assert_eq!(usdc_balance.borrowed_from_blend, 100_000);
+
+    // The withdrawn XLM went to the router and the USDC it returned into the pool
+    let token_balance = |token: &Address, holder: &Address| TokenClient::new(&t.env, token).balance(holder);
+    assert_eq!(token_balance(&xlm, &t.pool.address), 600_000);
+    assert_eq!(token_balance(&xlm, &router.address), 400_000);
+    assert_eq!(token_balance(&usdc, &router.address), 0);
+    assert_eq!(token_balance(&usdc, &t.pool.address), 480_000);
+    assert_eq!(token_balance(&xlm, &t.contract_id), 0);
+    assert_eq!(token_balance(&usdc, &t.contract_id), 0);
+}
+
+#[test]
+#[should_panic(expected = "Health factor too low")]
+fn test_swap_collateral_rejects_unhealthy_result() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let xlm = create_token(&t.env);
+    let usdc = create_token(&t.env);
+    setup_router(&t, 5000);
+
+    // Swapping at half value would leave 0.8 * 750_000 / 700_000 < 1.0
+    t.client.supply_to_blend(&user, &xlm, &1_000_000, &None);
+    t.client.borrow_against_dust(&user, &usdc, &700_000, &0);
+    t.client.swap_collateral(&user, &xlm, &usdc, &500_000, &0);
+}
+
+#[test]
+fn test_arbitrage_whitelist_restricts_until_disabled() {
+    let t = setup();
+    let bot = Address::generate(&t.env);
+    let stranger = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    // Freshly initialized contracts start restricted
+    t.env.as_contract(&t.contract_id, || {
+        let mut config: ContractConfig = t.env.storage().instance().get(&DataKey::Config).unwrap();
+        config.arbitrage_whitelist_enabled = true;
+        t.env.storage().instance().set(&DataKey::Config, &config);
+    });
+
+    assert!(t.client.try_flash_loan_arbitrage(&bot, &arbitrage_params(&t.env, &token, 1_000_000)).is_err());
+    t.client.admin_add_arbitrager(&t.admin, &bot);
+    assert_eq!(t.client.get_whitelisted_arbitragers(), Vec::from_array(&t.env, [bot.clone()]));
+    t.client.flash_loan_arbitrage(&bot, &arbitrage_params(&t.env, &token, 1_000_000));
+    assert!(t.client.try_flash_loan_arbitrage(&stranger, &arbitrage_params(&t.env, &token, 1_000_000)).is_err());
+
+    t.client.admin_remove_arbitrager(&t.admin, &bot);
+    assert!(t.client.try_flash_loan_arbitrage(&bot, &arbitrage_params(&t.env, &token, 1_000_000)).is_err());
+
+    t.client.admin_disable_whitelist(&t.admin);
+    assert!(!read_config(&t).arbitrage_whitelist_enabled);
+    t.client.flash_loan_arbitrage(&stranger, &arbitrage_params(&t.env, &token, 1_000_000));
+}
+
+#[test]
+fn test_health_factor_falls_back_to_twap_on_price_deviation() {
+    let t = setup();
+    let oracle = setup_oracle(&t);
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.admin_set_price_deviation_limit(&t.admin, &1000);
+
+    // Establish a $1.00 TWAP for the collateral
+    oracle.set_price(&collateral, &1_000_000);
+    t.env.as_contract(&t.contract_id, || {
+        let mut balances: Map<Address, UserBalance> = Map::new(&t.env);
+        balances.set(collateral.clone(), user_balance(&collateral, 0, 1_000_000, 0));
+        balances.set(debt.clone(), user_balance(&debt, 0, 0, 500_000));
+        t.env.storage().persistent().set(&DataKey::UserBalances(user.clone()), &balances);
+        DustAggregator::calculate_user_health_factor(&t.env, &user);
+    });
+
+    let health_factor_at = |spot: i128| {
+        t.env.ledger().with_mut(|li| li.sequence_number += 1);
+        oracle.set_price(&collateral, &spot);
+        t.env.as_contract(&t.contract_id, || {
+            // Hold the TWAP at $1.00 so each level is measured against the same average
+            t.env.storage().instance().set(&DataKey::PriceTwap(collateral.clone()), &PriceTwap { price: 1_000_000, ledger: 0 });
+            let health_factor = DustAggregator::calculate_user_health_factor(&t.env, &user);
+            let alert = dust_events(&t).into_iter().find(|event| matches!(event, DustEvent::PriceDeviationAlert(..)));
+            (health_factor, alert)
+        })
+    };
+
+    // 5% is within the 10% limit, so spot is used
+    let (health_factor, alert) = health_factor_at(1_050_000);
+    assert_eq!(health_factor, 1_680_000);
+    assert_eq!(alert, None);
+
+    // 15% and 25% fall back to the TWAP and raise an alert
+    let (health_factor, alert) = health_factor_at(1_150_000);
+    assert_eq!(health_factor, 1_600_000);
+    assert_eq!(alert, Some(DustEvent::PriceDeviationAlert(collateral.clone(), 1_150_000, 1_000_000, 1500)));
+
+    let (health_factor, alert) = health_factor_at(750_000);
+    assert_eq!(health_factor, 1_600_000);
+    assert_eq!(alert, Some(DustEvent::PriceDeviationAlert(collateral.clone(), 750_000, 1_000_000, 2500)));
+}
+
+#[test]
+fn test_revenue_share_split_by_supply() {
+    let t = setup();
+    let alice = Address::generate(&t.env);
+    let bob = Address::generate(&t.env);
+    let trader = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&alice, &token, &1000, &None);
+    t.client.supply_to_blend(&bob, &token, &3000, &None);
+    t.client.admin_set_revenue_share_pct(&t.admin, &5000);
+
+    // 15_000 profit at 1%: half of the 150 fee is shared
+    t.client.flash_loan_arbitrage(&trader, &arbitrage_params(&t.env, &token, 1_000_000));
+    assert_eq!(t.client.get_revenue_pool(&token), 75);
+    assert_eq!(t.client.get_collected_fees(&token), 75);
+
+    mint(&t.env, &token, &t.contract_id, 75);
+    assert_eq!(t.client.claim_revenue_share(&alice, &token), 18);
+    assert_eq!(t.client.claim_revenue_share(&bob, &token), 56);
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&alice), 18);
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&bob), 56);
+
+    // Nothing new to claim until more revenue arrives
+    assert_eq!(t.client.claim_revenue_share(&alice, &token), 0);
+}
+
+#[test]
+fn test_revenue_share_checkpointed_on_supply_changes() {
+    let t = setup();
+    let alice = Address::generate(&t.env);
+    let bob = Address::generate(&t.env);
+    let carol = Address::generate(&t.env);
+    let trader = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    t.client.admin_set_revenue_share_pct(&t.admin, &5000);
+
+    // Alice alone earns the first 75
+    t.client.supply_to_blend(&alice, &token, &1000, &None);
+    t.client.flash_loan_arbitrage(&trader, &arbitrage_params(&t.env, &token, 1_000_000));
+
+    // Bob arriving late does not dilute revenue that was already earned; the trader's volume now
+    // puts the second fee in the gold tier, so 56 is shared
+    t.client.supply_to_blend(&bob, &token, &1000, &None);
+    t.client.flash_loan_arbitrage(&trader, &arbitrage_params(&t.env, &token, 1_000_000));
+    assert_eq!(t.client.get_revenue_pool(&token), 75 + 56);
+
+    // Alice withdrawing keeps what she earned, and a fresh supplier cannot claim any of it
+    t.client.withdraw_from_blend(&alice, &token, &1000);
+    t.client.supply_to_blend(&carol, &token, &5000, &None);
+
+    mint(&t.env, &token, &t.contract_id, 131);
+    assert_eq!(t.client.claim_revenue_share(&alice, &token), 75 + 28);
+    assert_eq!(t.client.claim_revenue_share(&bob, &token), 28);
+    assert_eq!(t.client.claim_revenue_share(&carol, &token), 0);
+    assert_eq!(t.client.claim_revenue_share(&bob, &token), 0);
+    assert!(t.client.try_claim_revenue_share(&alice, &token).is_err());
+}
+
+#[test]
+fn test_batch_get_user_balances_mixes_known_and_missing() {
+    let t = setup();
+    let alice = Address::generate(&t.env);
+    let bob = Address::generate(&t.env);
+    let token_a = create_token(&t.env);
+    let token_b = create_token(&t.env);
+
+    t.client.supply_to_blend(&alice, &token_a, &1000, &None);
+    t.client.supply_to_blend(&bob, &token_b, &250, &None);
+
+    let balances = t.client.batch_get_user_balances(&Vec::from_array(&t.env, [
+        (alice.clone(), token_a.clone()),
+        (alice.clone(), token_b.clone()),
+        (bob.clone(), token_b.clone()),
+    ]));
+    assert_eq!(balances.len(), 3);
+    assert_eq!(balances.get(0).unwrap(), t.client.get_user_balance(&alice, &token_a));
+    assert_eq!(balances.get(0).unwrap().supplied_to_blend, 1000);
+    assert_eq!(balances.get(1).unwrap().token, token_b);
+    assert_eq!(balances.get(1).unwrap().supplied_to_blend, 0);
+    assert_eq!(balances.get(1).unwrap().interaction_count, 0);
+    assert_eq!(balances.get(2).unwrap().supplied_to_blend, 250);
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_batch_get_user_balances_caps_queries() {
+    let t = setup();
+    let token = create_token(&t.env);
+    let mut queries = Vec::new(&t.env);
+    for _ in 0..(MAX_BALANCE_QUERIES + 1) {
+        queries.push_back((Address::generate(&t.env), token.clone()));
+    }
+    t.client.batch_get_user_balances(&queries);
+}
+
+#[test]
+fn test_pool_factory_pools_cached_until_invalidated() {
+    let t = setup();
+    let factory = Address::from_string(&String::from_str(&t.env, BLEND_POOL_FACTORY));
+    let pool_a = Address::generate(&t.env);
+    let pool_b = Address::generate(&t.env);
+    let pool_c = Address::generate(&t.env);
+    let candidates = Vec::from_array(&t.env, [pool_a.clone(), pool_b.clone(), pool_c.clone()]);
+
+    t.factory.set_pool(&pool_a, &true);
+    t.factory.set_pool(&pool_c, &true);
+
+    let pools = t.client.admin_refresh_pool_cache(&t.admin, &factory, &candidates);
+    assert_eq!(pools, Vec::from_array(&t.env, [pool_a.clone(), pool_c.clone()]));
+    assert!(dust_events(&t).contains(&DustEvent::PoolCacheRefreshed(factory.clone(), 2)));
+
+    // Cached pools are served without asking the factory again
+    t.factory.set_pool(&pool_a, &false);
+    assert_eq!(t.client.get_pool_factory_pools(&factory, &candidates), pools);
+
+    t.client.invalidate_pool_cache(&t.admin, &factory);
+    assert_eq!(
+        t.client.get_pool_factory_pools(&factory, &candidates),
+        Vec::from_array(&t.env, [pool_c])
+    );
+}
+
+#[test]
+fn test_pool_factory_pools_lookup_writes_nothing() {
+    let t = setup();
+    let factory = Address::from_string(&String::from_str(&t.env, BLEND_POOL_FACTORY));
+    let pool_a = Address::generate(&t.env);
+    let pool_b = Address::generate(&t.env);
+    t.factory.set_pool(&pool_a, &true);
+    t.factory.set_pool(&pool_b, &true);
+
+    // An early caller passing a single candidate does not pin the list others see
+    assert_eq!(t.client.get_pool_factory_pools(&factory, &Vec::from_array(&t.env, [pool_a.clone()])), Vec::from_array(&t.env, [pool_a.clone()]));
+    assert!(dust_events(&t).is_empty());
+    t.env.as_contract(&t.contract_id, || {
+        assert!(!t.env.storage().instance().has(&PoolKey::ValidatedPools(factory.clone())));
+    });
+    assert_eq!(
+        t.client.get_pool_factory_pools(&factory, &Vec::from_array(&t.env, [pool_a.clone(), pool_b.clone()])),
+        Vec::from_array(&t.env, [pool_a.clone(), pool_b.clone()])
+    );
+
+    // Nor can a candidate the factory rejects be slipped in
+    let fake = Address::generate(&t.env);
+    assert_eq!(
+        t.client.get_pool_factory_pools(&factory, &Vec::from_array(&t.env, [fake, pool_b.clone()])),
+        Vec::from_array(&t.env, [pool_b])
+    );
+
+    // Lookups are bounded
+    let mut candidates = Vec::new(&t.env);
+    for _ in 0..(MAX_POOL_CANDIDATES + 1) {
+        candidates.push_back(Address::generate(&t.env));
+    }
+    assert!(t.client.try_get_pool_factory_pools(&factory, &candidates).is_err());
+    assert!(t.client.try_admin_refresh_pool_cache(&t.admin, &factory, &candidates).is_err());
+}
+
+#[test]
+fn test_supply_native_xlm_wraps_and_supplies() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let native = create_token(&t.env);
+
+    assert_eq!(t.client.get_wrapped_xlm(), Address::from_string(&String::from_str(&t.env, XLM_TOKEN)));
+    t.client.admin_set_wrapped_xlm(&t.admin, &native);
+    t.client.admin_add_token(&t.admin, &native, &token_config(0), &None);
+    mint(&t.env, &native, &user, 5000);
+
+    t.client.supply_native_xlm(&user, &2000);
+    assert!(dust_events(&t).contains(&DustEvent::NativeXlmWrappedAndSupplied(user.clone(), 2000)));
+
+    assert_eq!(TokenClient::new(&t.env, &native).balance(&user), 3000);
+    assert_eq!(TokenClient::new(&t.env, &native).balance(&t.contract_id), 2000);
+    assert_eq!(t.client.get_user_balance(&user, &native).supplied_to_blend, 2000);
+}
+
+#[test]
+#[should_panic(expected = "Token not supported")]
+fn test_supply_native_xlm_requires_supported_token() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let native = create_token(&t.env);
+
+    t.client.admin_set_wrapped_xlm(&t.admin, &native);
+    mint(&t.env, &native, &user, 5000);
+    t.client.supply_native_xlm(&user, &2000);
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_supply_native_xlm_rejects_zero() {
+    let t = setup();
+    t.client.supply_native_xlm(&Address::generate(&t.env), &0);
+}
+
+#[test]
+fn test_config_views_track_initialize_and_admin_updates() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    let config = t.client.get_config();
+    assert_eq!(config, read_config(&t));
+    assert_eq!(config.admin, t.admin);
+    assert_eq!(config.fee_rate, 100);
+    assert_eq!(config.grace_period_ledgers, DEFAULT_GRACE_PERIOD_LEDGERS);
+
+    let blend_config = t.client.get_blend_config();
+    assert_eq!(blend_config, read_blend_config(&t));
+    assert_eq!(blend_config.pool_address, t.pool.address);
+    assert_eq!(blend_config.min_health_factor, HEALTH_FACTOR_SCALE);
+
+    t.client.admin_set_grace_period(&t.admin, &500);
+    t.client.admin_set_health_thresholds(&t.admin, &(HEALTH_FACTOR_SCALE * 11 / 10), &(HEALTH_FACTOR_SCALE * 2));
+    t.client.supply_to_blend(&user, &token, &1000, &None);
+    assert_eq!(t.client.get_config().grace_period_ledgers, 500);
+    assert_eq!(t.client.get_blend_config().min_health_factor, HEALTH_FACTOR_SCALE * 11 / 10);
+
+    let state = t.client.get_full_state();
+    assert_eq!(state.config, t.client.get_config());
+    assert_eq!(state.blend_config, t.client.get_blend_config());
+    let (total_tvl, total_yield, active_users) = t.client.get_stats();
+    assert_eq!((state.total_tvl, state.total_yield, state.active_users), (total_tvl, total_yield, active_users));
+    assert_eq!(state.active_users, 1);
+    assert_eq!(state.version, t.client.get_contract_version());
+}
+
+#[test]
+fn test_admin_validate_user_balances_detects_drift() {
+    let t = setup();
+    let alice = Address::generate(&t.env);
+    let bob = Address::generate(&t.env);
+    let token_a = create_token(&t.env);
+    let token_b = create_token(&t.env);
+
+    t.client.supply_to_blend(&alice, &token_a, &1000, &None);
+    t.client.supply_to_blend(&alice, &token_b, &500, &None);
+    t.client.supply_to_blend(&bob, &token_a, &1001, &None);
+
+    // Blend reports token_b short by 50, and token_a within rounding tolerance
+    t.pool.set_position(&position(&t.env, &[(token_a.clone(), 1000), (token_b.clone(), 450)], &[]));
+
+    let mismatches = t.client.admin_validate_user_balances(&t.admin, &Vec::from_array(&t.env, [alice.clone(), bob.clone()]));
+    assert!(dust_events(&t).contains(&DustEvent::BalanceMismatchFound(alice.clone(), token_b.clone(), -50)));
+    assert_eq!(mismatches, Vec::from_array(&t.env, [BalanceMismatch {
+        user: alice,
+        token: token_b,
+        stored_value: 500,
+        actual_value: 450,
+        delta: -50,
+    }]));
+}
+
+#[test]
+#[should_panic(expected = "Invalid amount")]
+fn test_admin_validate_user_balances_caps_users() {
+    let t = setup();
+    let mut users = Vec::new(&t.env);
+    for _ in 0..(MAX_BALANCE_AUDIT_USERS + 1) {
+        users.push_back(Address::generate(&t.env));
+    }
+    t.client.admin_validate_user_balances(&t.admin, &users);
+}
+
+#[test]
+fn test_arbitrage_min_profit_floors_enforced() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    // Below the 1% hard floor
+    let mut params = arbitrage_params(&t.env, &token, 1_000_000);
+    params.min_profit = 9_999;
+    assert!(t.client.try_flash_loan_arbitrage(&user, &params).is_err());
+
+    // The admin floor is enforced above the user's value
+    t.client.admin_set_min_arbitrage_profit(&t.admin, &token, &12_000);
+    assert_eq!(t.client.get_min_arbitrage_profit(&token), 12_000);
+    params.min_profit = 10_000;
+    assert!(t.client.try_flash_loan_arbitrage(&user, &params).is_err());
+
+    params.min_profit = 12_000;
+    t.client.flash_loan_arbitrage(&user, &params);
+
+    // Other tokens only face the hard floor
+    t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &create_token(&t.env), 1_000_000));
+}
+
+#[test]
+fn test_auto_repay_restores_health_factor_after_borrow() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    assert!(t.client.try_set_auto_repay_threshold(&user, &debt, &(HEALTH_FACTOR_SCALE * 2), &(HEALTH_FACTOR_SCALE * 2)).is_err());
+    t.client.set_auto_repay_threshold(&user, &debt, &(HEALTH_FACTOR_SCALE * 2), &(HEALTH_FACTOR_SCALE * 5 / 2));
+
+    mint(&t.env, &debt, &user, 200_000);
+    TokenClient::new(&t.env, &debt).approve(&user, &t.contract_id, &200_000, &(t.env.ledger().sequence() + 1000));
+    t.client.supply_to_blend(&user, &collateral, &1_000_000, &None);
+
+    // 300_000 of debt leaves the health factor at 2.67, above the trigger
+    t.client.borrow_against_dust(&user, &debt, &300_000, &0);
+    assert_eq!(t.client.get_user_balance(&user, &debt).borrowed_from_blend, 300_000);
+
+    // 450_000 drops it to 1.78; repaying down to 320_000 restores 2.5
+    t.client.borrow_against_dust(&user, &debt, &150_000, &0);
+    assert!(dust_events(&t).contains(&DustEvent::AutoRepayTriggered(user.clone(), debt.clone(), 130_000, HEALTH_FACTOR_SCALE * 5 / 2)));
+    assert_eq!(t.client.get_user_balance(&user, &debt).borrowed_from_blend, 320_000);
+    assert_eq!(TokenClient::new(&t.env, &debt).balance(&user), 70_000);
+}
+
+#[test]
+fn test_oracle_price_cached_within_ttl() {
+    let t = setup();
+    let oracle = setup_oracle(&t);
+    let token = create_token(&t.env);
+    oracle.set_price(&token, &1_000_000);
+
+    let price_calls_for_two_reads = |t: &TestSetup| {
+        let calls_before = oracle.price_calls();
+        t.env.as_contract(&t.contract_id, || {
+            assert_eq!(DustAggregator::get_token_price_usd(&t.env, &token), 1_000_000);
+            assert_eq!(DustAggregator::get_token_price_usd(&t.env, &token), 1_000_000);
+        });
+        oracle.price_calls() - calls_before
+    };
+
+    // Same ledger: the second read is served from cache
+    assert_eq!(price_calls_for_two_reads(&t), 1);
+
+    // A new ledger refetches with the default TTL
+    t.env.ledger().with_mut(|li| li.sequence_number += 1);
+    assert_eq!(price_calls_for_two_reads(&t), 1);
+
+    // Within a longer TTL the cached read carries over
+    t.client.admin_set_price_cache_ttl(&t.admin, &10);
+    assert_eq!(t.client.get_blend_config().cache_ttl_ledgers, 10);
+    t.env.ledger().with_mut(|li| li.sequence_number += 5);
+    assert_eq!(price_calls_for_two_reads(&t), 0);
+
+    t.env.ledger().with_mut(|li| li.sequence_number += 10);
+    assert_eq!(price_calls_for_two_reads(&t), 1);
+}
+
+#[test]
+fn test_supply_with_auto_borrow_opens_leveraged_position() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+    let router = setup_router(&t, 10000);
+
+    assert!(t.client.try_supply_with_auto_borrow(&user, &collateral, &1_000_000, &debt, &(MAX_LEVERAGE_RATIO_BPS + 1)).is_err());
+
+    // The user's deposit is held by the contract; the pool lends and the router sells real tokens
+    mint(&t.env, &collateral, &t.contract_id, 1_000_000);
+    mint(&t.env, &debt, &t.pool.address, 500_000);
+    mint(&t.env, &collateral, &router.address, 500_000);
+    t.pool.set_move_tokens(&true);
+
+    let total_supplied = t.client.supply_with_auto_borrow(&user, &collateral, &1_000_000, &debt, &5000);
+    assert_eq!(total_supplied, 1_500_000);
+    assert!(dust_events(&t).contains(&DustEvent::LeveragedPositionOpened(
+        user.clone(), collateral.clone(), 1_500_000, debt.clone(), 500_000
+    )));
+
+    assert_eq!(t.client.get_user_balance(&user, &collateral).supplied_to_blend, 1_500_000);
+    assert_eq!(t.client.get_user_balance(&user, &debt).borrowed_from_blend, 500_000);
+    assert_eq!(t.client.get_user_balance(&user, &debt).balance, 0);
+
+    let collateral_token = TokenClient::new(&t.env, &collateral);
+    let debt_token = TokenClient::new(&t.env, &debt);
+    assert_eq!(collateral_token.balance(&t.pool.address), 1_500_000);
+    assert_eq!(debt_token.balance(&router.address), 500_000);
+    assert_eq!(collateral_token.balance(&t.contract_id), 0);
+    assert_eq!(debt_token.balance(&t.contract_id), 0);
+
+    // 1_500_000 of collateral at 80% against 500_000 of debt
+    let health_factor = t.env.as_contract(&t.contract_id, || DustAggregator::calculate_user_health_factor(&t.env, &user));
+    assert_eq!(health_factor, HEALTH_FACTOR_SCALE * 12 / 5);
+}
+
+#[test]
+fn test_token_metadata_stored_on_add() {
+    let t = setup();
+    let usdc = create_token(&t.env);
+    let sac = create_token(&t.env);
+    let bare = Address::generate(&t.env);
+
+    let metadata = TokenMetadata {
+        symbol: Symbol::new(&t.env, "USDC"),
+        decimals: 7,
+        display_name: String::from_str(&t.env, "USD Coin"),
+    };
+    t.client.admin_add_token(&t.admin, &usdc, &token_config(0), &Some(metadata.clone()));
+    assert_eq!(t.client.get_token_metadata(&usdc), Some(metadata.clone()));
+
+    // Read from the token contract when not supplied
+    t.client.admin_add_token(&t.admin, &sac, &token_config(0), &None);
+    let sac_client = TokenClient::new(&t.env, &sac);
+    let read = t.client.get_token_metadata(&sac).unwrap();
+    assert_eq!(read.decimals, sac_client.decimals());
+    assert_eq!(read.display_name, sac_client.name());
+
+    // An address with no token interface has none
+    t.client.admin_add_token(&t.admin, &bare, &token_config(0), &None);
+    assert_eq!(t.client.get_token_metadata(&bare), None);
+
+    assert_eq!(
+        t.client.get_all_token_metadata(),
+        Vec::from_array(&t.env, [(usdc, metadata), (sac, read)])
+    );
+}
+
+#[test]
+fn test_close_position_repays_debt_with_interest_and_returns_collateral() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+    let router = setup_router(&t, 20000);
+
+    t.client.supply_to_blend(&user, &collateral, &1_000_000, &None);
+    t.client.borrow_against_dust(&user, &debt, &400_000, &0);
+    mint(&t.env, &collateral, &t.pool.address, 1_000_000);
+    mint(&t.env, &debt, &router.address, 440_000);
+    t.pool.set_move_tokens(&true);
+
+    // Blend has accrued 10% interest on the 400_000 borrowed
+    t.pool.set_position(&position(&t.env, &[(collateral.clone(), 1_000_000)], &[(debt.clone(), 440_000)]));
+
+    // 220_000 collateral at 2:1 covers the 440_000 owed
+    let returned = t.client.close_position(&user, &collateral, &debt);
+    assert_eq!(returned, 780_000);
+    assert!(dust_events(&t).contains(&DustEvent::PositionClosed(user.clone(), collateral.clone(), debt.clone(), 780_000, 440_000)));
+
+    let collateral_token = TokenClient::new(&t.env, &collateral);
+    let debt_token = TokenClient::new(&t.env, &debt);
+    assert_eq!(collateral_token.balance(&user), 780_000);
+    assert_eq!(collateral_token.balance(&router.address), 220_000);
+    assert_eq!(debt_token.balance(&t.pool.address), 440_000);
+    assert_eq!(collateral_token.balance(&t.contract_id), 0);
+    assert_eq!(debt_token.balance(&t.contract_id), 0);
+
+    let submitted = t.pool.submitted();
+    let request_types: std::vec::Vec<u32> = submitted.iter().skip(submitted.len() as usize - 2).map(|r| r.request_type).collect();
+    assert_eq!(request_types, std::vec![REQUEST_WITHDRAW_COLLATERAL, REQUEST_REPAY]);
+
+    let collateral_balance = t.client.get_user_balance(&user, &collateral);
+    assert_eq!(collateral_balance.supplied_to_blend, 0);
+    assert_eq!(collateral_balance.balance, 0);
+    let debt_balance = t.client.get_user_balance(&user, &debt);
+    assert_eq!(debt_balance.borrowed_from_blend, 0);
+    assert_eq!(debt_balance.realized_pnl, -40_000);
+
+    // Nothing left to close
+    assert!(t.client.try_close_position(&user, &collateral, &debt).is_err());
+}
+
+#[test]
+fn test_close_position_without_debt_only_withdraws() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let untouched = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &collateral, &1_000, &None);
+    mint(&t.env, &collateral, &t.pool.address, 1_000);
+    t.pool.set_move_tokens(&true);
+
+    // The debt token was never used, and nothing is repaid
+    assert_eq!(t.client.close_position(&user, &collateral, &untouched), 1_000);
+    assert_eq!(TokenClient::new(&t.env, &collateral).balance(&user), 1_000);
+    let last = t.pool.submitted().last().unwrap();
+    assert_eq!((last.request_type, last.address, last.amount), (REQUEST_WITHDRAW_COLLATERAL, collateral.clone(), 1_000));
+}
+
+#[test]
+#[should_panic(expected = "Health factor too low")]
+fn test_close_position_rejects_leaving_other_debt_unbacked() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+    let other_debt = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &collateral, &1_000, &None);
+    t.client.borrow_against_dust(&user, &other_debt, &500, &0);
+    mint(&t.env, &collateral, &t.pool.address, 1_000);
+    t.pool.set_move_tokens(&true);
+
+    // Nothing is owed in `debt`, but the collateral still backs the other borrow
+    t.client.close_position(&user, &collateral, &debt);
+}
+
+#[test]
+fn test_governance_proposal_lifecycle() {
+    let t = setup();
+    let voters: std::vec::Vec<Address> = (0..3).map(|_| Address::generate(&t.env)).collect();
+
+    let fee_rate = Symbol::new(&t.env, "fee_rate");
+    assert!(t.client.try_admin_create_proposal(&t.admin, &Symbol::new(&t.env, "unknown"), &1, &100).is_err());
+    let proposal_id = t.client.admin_create_proposal(&t.admin, &fee_rate, &250, &100);
+    assert_eq!(governance_events(&t), std::vec![GovernanceEvent::ProposalCreated(proposal_id.clone(), fee_rate.clone(), 250, 100)]);
+
+    t.client.vote(&voters[0], &proposal_id, &true);
+    t.client.vote(&voters[1], &proposal_id, &true);
+    t.client.vote(&voters[2], &proposal_id, &false);
+    assert!(t.client.try_vote(&voters[0], &proposal_id, &false).is_err());
+
+    let proposal = t.client.get_proposal(&proposal_id).unwrap();
+    assert_eq!((proposal.votes_for, proposal.votes_against), (2, 1));
+
+    // Not executable while voting is open
+    assert!(t.client.try_execute_proposal(&proposal_id).is_err());
+
+    t.env.ledger().with_mut(|li| li.sequence_number += 101);
+    assert!(t.client.try_vote(&Address::generate(&t.env), &proposal_id, &true).is_err());
+    assert!(t.client.execute_proposal(&proposal_id));
+    assert_eq!(governance_events(&t), std::vec![GovernanceEvent::ProposalExecuted(proposal_id.clone(), true)]);
+    assert!(t.client.get_proposal(&proposal_id).unwrap().executed);
+    assert!(t.client.try_execute_proposal(&proposal_id).is_err());
+
+    // The passed rate waits out the fee change time lock like any other fee proposal
+    assert_eq!(read_config(&t).fee_rate, 100);
+    assert_eq!(
+        t.client.get_pending_fee_rate(),
+        Some(PendingFeeRate { rate: 250, effective_after_ledger: t.env.ledger().sequence() + FEE_CHANGE_DELAY_LEDGERS })
+    );
+    t.env.ledger().with_mut(|li| li.sequence_number += FEE_CHANGE_DELAY_LEDGERS + 1);
+    assert_eq!(t.client.get_tier_fee_rate(&t.admin), 250);
+    assert_eq!(t.client.get_pending_fee_rate(), None);
+}
+
+#[test]
+fn test_governance_proposal_rejected_without_majority() {
+    let t = setup();
+    let grace_period = Symbol::new(&t.env, "grace_period");
+    let proposal_id = t.client.admin_create_proposal(&t.admin, &grace_period, &10, &50);
+
+    t.client.vote(&Address::generate(&t.env), &proposal_id, &true);
+    t.client.vote(&Address::generate(&t.env), &proposal_id, &false);
+
+    t.env.ledger().with_mut(|li| li.sequence_number += 51);
+    assert!(!t.client.execute_proposal(&proposal_id));
+    assert_eq!(read_config(&t).grace_period_ledgers, DEFAULT_GRACE_PERIOD_LEDGERS);
+}
+
+#[test]
+fn test_max_ltv_enforced_at_boundaries() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.admin_add_token(&t.admin, &debt, &token_config(0), &None);
+    t.client.supply_to_blend(&user, &collateral, &1_000_000, &None);
+    assert_eq!(t.client.get_current_ltv(&user, &debt), 0);
+
+    for (max_ltv_bps, borrow_to) in [(5000u32, 500_000i128), (8000, 800_000), (10000, 1_000_000)] {
+        t.client.admin_set_token_ltv(&t.admin, &debt, &max_ltv_bps);
+        let borrowed = t.client.get_user_balance(&user, &debt).borrowed_from_blend;
+
+        // One unit past the ceiling is rejected, landing exactly on it is allowed
+        assert!(t.client.try_borrow_against_dust(&user, &debt, &(borrow_to - borrowed + 1), &0).is_err());
+        t.client.borrow_against_dust(&user, &debt, &(borrow_to - borrowed), &0);
+        assert_eq!(t.client.get_current_ltv(&user, &debt), max_ltv_bps);
+    }
+}
+
+#[test]
+fn test_admin_emergency_drain_only_in_emergency_mode() {
+    let t = setup();
+    let token = create_token(&t.env);
+    mint(&t.env, &token, &t.contract_id, 12_345);
+
+    assert!(t.client.try_admin_emergency_drain(&t.admin, &token).is_err());
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&t.contract_id), 12_345);
+
+    t.client.admin_set_emergency(&t.admin, &true);
+    assert_eq!(t.client.admin_emergency_drain(&t.admin, &token), 12_345);
+    let drained = t.env.events().all().iter().any(|(_, _, data)| {
+        EmergencyEvent::try_from_val(&t.env, &data) == Ok(EmergencyEvent::EmergencyDrain(t.admin.clone(), token.clone(), 12_345))
+    });
+    assert!(drained);
+
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&t.contract_id), 0);
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&t.admin), 12_345);
+    assert_eq!(
+        t.client.get_emergency_drain_log(),
+        Vec::from_array(&t.env, [(token, 12_345, t.env.ledger().sequence())])
+    );
+}
+
+#[test]
+fn test_admin_emergency_drain_clears_earmarked_balances() {
+    let t = setup();
+    let supplier = Address::generate(&t.env);
+    let trader = Address::generate(&t.env);
+    let donor = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    // Fees, shared revenue, insurance and keeper rewards all sit in the same token balance
+    t.client.supply_to_blend(&supplier, &token, &1000, &None);
+    t.client.admin_set_revenue_share_pct(&t.admin, &5000);
+    t.client.flash_loan_arbitrage(&trader, &arbitrage_params(&t.env, &token, 1_000_000));
+    mint(&t.env, &token, &t.contract_id, 150);
+    mint(&t.env, &token, &donor, 300);
+    t.client.donate_to_insurance_fund(&donor, &token, &200);
+    t.client.admin_set_keeper_reward(&t.admin, &KeeperReward { token: token.clone(), amount: 25 });
+    t.client.fund_keeper_reward_pool(&donor, &100);
+
+    t.client.admin_set_emergency(&t.admin, &true);
+    assert_eq!(t.client.admin_emergency_drain(&t.admin, &token), 450);
+
+    assert_eq!(t.client.get_collected_fees(&token), 0);
+    assert_eq!(t.client.get_insurance_fund(&token), 0);
+    assert_eq!(t.client.get_keeper_reward_pool(), 0);
+    assert_eq!(t.client.claim_revenue_share(&supplier, &token), 0);
+}
+
+#[test]
+fn test_lifetime_stats_accumulate_per_user_and_protocol() {
+    let t = setup();
+    let alice = Address::generate(&t.env);
+    let bob = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    mint(&t.env, &token, &t.contract_id, 10_000);
+
+    t.client.supply_to_blend(&alice, &token, &1000, &None);
+    t.client.borrow_against_dust(&alice, &token, &300, &0);
+    t.client.repay_blend_debt(&alice, &token, &100);
+    t.client.withdraw_from_blend(&alice, &token, &200);
+    t.client.supply_to_blend(&alice, &token, &500, &None);
+    t.client.supply_to_blend(&bob, &token, &2000, &None);
+
+    assert_eq!(t.client.get_user_lifetime_stats(&alice), UserLifetimeStats {
+        total_supplied: 1500,
+        total_borrowed: 300,
+        total_repaid: 100,
+        total_withdrawn: 200,
+        total_yield_claimed: 0,
+    });
+
+    // Repaying and withdrawing never reduce the supply or borrow counters
+    let protocol = t.client.get_protocol_lifetime_stats();
+    assert_eq!(protocol.total_supplied, 3500);
+    assert_eq!(protocol.total_borrowed, 300);
+    assert_eq!(t.client.get_user_lifetime_stats(&Address::generate(&t.env)), UserLifetimeStats::default());
+}
+
+#[test]
+fn test_recommended_repay_amount_reaches_target_health_factor() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+    let other_debt = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &collateral, &1_000_000, &None);
+    assert_eq!(t.client.get_recommended_repay_amount(&user, &debt, &(2 * HEALTH_FACTOR_SCALE)), 0);
+
+    // 1_000_000 collateral at 80% against 700_000 debt is HF ~1.14
+    t.client.borrow_against_dust(&user, &debt, &700_000, &0);
+    assert_eq!(t.client.get_recommended_repay_amount(&user, &debt, &(HEALTH_FACTOR_SCALE * 11 / 10)), 0);
+
+    // Target 1.5 leaves 800_000 / 1.5 = 533_333 of debt, 2.0 leaves 400_000
+    assert_eq!(t.client.get_recommended_repay_amount(&user, &debt, &(HEALTH_FACTOR_SCALE * 3 / 2)), 166_667);
+    assert_eq!(t.client.get_recommended_repay_amount(&user, &debt, &(2 * HEALTH_FACTOR_SCALE)), 300_000);
+    assert_eq!(t.client.get_recommended_repay_amount(&user, &debt, &(4 * HEALTH_FACTOR_SCALE)), 500_000);
+
+    // When repaying this token alone cannot reach the target, the whole debt is recommended
+    t.client.borrow_against_dust(&user, &other_debt, &100_000, &0);
+    assert_eq!(t.client.get_recommended_repay_amount(&user, &other_debt, &(2 * HEALTH_FACTOR_SCALE)), 100_000);
+
+    let target = HEALTH_FACTOR_SCALE * 3 / 2;
+    let recommended = t.client.get_recommended_repay_amount(&user, &debt, &target);
+    assert_eq!(recommended, 266_667);
+    t.client.repay_blend_debt(&user, &debt, &recommended);
+    let health_factor = t.env.as_contract(&t.contract_id, || DustAggregator::calculate_user_health_factor(&t.env, &user));
+    assert!(health_factor >= target);
+    assert_eq!(t.client.get_recommended_repay_amount(&user, &debt, &target), 0);
+}
+
+#[test]
+fn test_yield_reinvestment_target_swaps_and_supplies() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let target = create_token(&t.env);
+    let router = setup_router(&t, 15000);
+
+    accrue_half_year_yield(&t, &user, &token);
+    mint(&t.env, &token, &t.pool.address, 50_000);
+    mint(&t.env, &target, &router.address, 75_000);
+    t.pool.set_move_tokens(&true);
+    assert_eq!(t.client.get_yield_reinvestment_target(&user, &token), None);
+    t.client.set_yield_reinvestment_target(&user, &token, &target);
+    assert_eq!(t.client.get_yield_reinvestment_target(&user, &token), Some(target.clone()));
+    assert_eq!(t.client.auto_compound(&user, &token), 50_000);
+
+    // The yield leaves the token position and is supplied as the target at the router's 1.5x rate
+    let topic = Symbol::new(&t.env, "CompoundEvent");
+    let reinvested = t.env.events().all().iter().any(|(_, topics, data)| {
+        topics.get(0).is_some_and(|first| Symbol::try_from_val(&t.env, &first) == Ok(topic.clone()))
+            && CompoundEvent::try_from_val(&t.env, &data)
+                == Ok(CompoundEvent::YieldReinvested(user.clone(), token.clone(), target.clone(), 50_000, 75_000))
+    });
+    assert!(reinvested);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 1_000_000);
+    assert_eq!(t.client.get_user_balance(&user, &target).supplied_to_blend, 75_000);
+    let supply = t.pool.submitted().last().unwrap();
+    assert_eq!((supply.request_type, supply.address, supply.amount), (REQUEST_DEPOSIT_COLLATERAL, target.clone(), 75_000));
+
+    // The harvested interest was actually sold and only the router's output was supplied
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&router.address), 50_000);
+    assert_eq!(TokenClient::new(&t.env, &target).balance(&t.pool.address), 75_000);
+    assert_eq!(TokenClient::new(&t.env, &token).balance(&t.contract_id), 0);
+    assert_eq!(TokenClient::new(&t.env, &target).balance(&t.contract_id), 0);
+
+    // Pointing the target back at the yield token restores plain re-supply
+    t.client.set_yield_reinvestment_target(&user, &token, &token);
+    assert_eq!(t.client.get_yield_reinvestment_target(&user, &token), None);
+    t.env.ledger().with_mut(|li| li.timestamp += (SECONDS_PER_YEAR / 2) as u64);
+    assert_eq!(t.client.auto_compound(&user, &token), 50_000);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 1_050_000);
+    assert_eq!(t.client.get_user_balance(&user, &target).supplied_to_blend, 75_000);
+}
+
+fn large_withdrawal_events(t: &TestSetup) -> std::vec::Vec<LargeWithdrawalEvent> {
+    let topic = Symbol::new(&t.env, "LargeWithdrawalEvent");
+    let mut events = std::vec::Vec::new();
+    for (_, topics, data) in t.env.events().all().iter() {
+        if topics.get(0).is_some_and(|first| Symbol::try_from_val(&t.env, &first) == Ok(topic.clone())) {
+            events.push(LargeWithdrawalEvent::try_from_val(&t.env, &data).unwrap());
+        }
+    }
+    events
+}
+
+#[test]
+fn test_large_withdrawal_waits_for_admin_approval() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1_000_000, &None);
+    t.client.admin_set_large_withdrawal_gate(&t.admin, &true, &100_000);
+    let config = read_config(&t);
+    assert!(config.large_withdrawal_gate);
+    assert_eq!(config.large_withdrawal_threshold, 100_000);
+
+    // At the threshold the withdrawal still goes straight through
+    t.client.withdraw_from_blend(&user, &token, &100_000);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 900_000);
+
+    t.client.withdraw_from_blend(&user, &token, &400_000);
+    assert_eq!(large_withdrawal_events(&t), std::vec![LargeWithdrawalEvent::Pending(user.clone(), token.clone(), 400_000, 0)]);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 900_000);
+    assert_eq!(t.client.get_pending_withdraw_approval(&user, &token, &0).unwrap().amount, 400_000);
+
+    t.client.admin_approve_withdrawal(&t.admin, &user, &token, &0);
+    assert_eq!(large_withdrawal_events(&t), std::vec![LargeWithdrawalEvent::Approved(user.clone(), token.clone(), 400_000, 0)]);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 500_000);
+    assert_eq!(t.client.get_pending_withdraw_approval(&user, &token, &0), None);
+    assert!(t.client.try_admin_approve_withdrawal(&t.admin, &user, &token, &0).is_err());
+}
+
+#[test]
+fn test_large_withdrawal_denied_keeps_funds_supplied() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &token, &1_000_000, &None);
+    t.client.admin_set_large_withdrawal_gate(&t.admin, &true, &100_000);
+    t.client.withdraw_from_blend(&user, &token, &200_000);
+    t.client.withdraw_from_blend(&user, &token, &300_000);
+
+    t.client.admin_deny_withdrawal(&t.admin, &user, &token, &1);
+    assert_eq!(large_withdrawal_events(&t), std::vec![LargeWithdrawalEvent::Denied(user.clone(), token.clone(), 300_000, 1)]);
+    assert_eq!(t.client.get_pending_withdraw_approval(&user, &token, &1), None);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 1_000_000);
+    assert!(t.client.try_admin_approve_withdrawal(&t.admin, &user, &token, &1).is_err());
+
+    // Turning the gate off releases new withdrawals, the earlier request still waits
+    t.client.admin_set_large_withdrawal_gate(&t.admin, &false, &100_000);
+    t.client.withdraw_from_blend(&user, &token, &300_000);
+    assert_eq!(t.client.get_user_balance(&user, &token).supplied_to_blend, 700_000);
+    assert_eq!(t.client.get_pending_withdraw_approval(&user, &token, &0).unwrap().amount, 200_000);
+}
+
+fn tvl_events(t: &TestSetup) -> std::vec::Vec<TvlEvent> {
+    let topic = Symbol::new(&t.env, "TvlEvent");
+    let mut events = std::vec::Vec::new();
+    for (_, topics, data) in t.env.events().all().iter() {
+        if topics.get(0).is_some_and(|first| Symbol::try_from_val(&t.env, &first) == Ok(topic.clone())) {
+            events.push(TvlEvent::try_from_val(&t.env, &data).unwrap());
+        }
+    }
+    events
+}
+
+#[test]
+fn test_tvl_milestone_event_fires_once() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.admin_add_tvl_milestone(&t.admin, &1_000_000);
+    t.client.admin_add_tvl_milestone(&t.admin, &5_000_000);
+    assert!(t.client.try_admin_add_tvl_milestone(&t.admin, &1_000_000).is_err());
+    assert!(t.client.try_admin_add_tvl_milestone(&t.admin, &0).is_err());
+    assert_eq!(t.client.get_tvl_milestones(), Vec::from_array(&t.env, [1_000_000i128, 5_000_000]));
+
+    t.client.supply_to_blend(&user, &token, &600_000, &None);
+    assert!(tvl_events(&t).is_empty());
+
+    let ledger = t.env.ledger().sequence();
+    t.client.supply_to_blend(&user, &token, &600_000, &None);
+    assert_eq!(tvl_events(&t), std::vec![TvlEvent::TvlMilestoneReached(1_000_000, 1_200_000, ledger)]);
+    assert_eq!(t.client.get_crossed_tvl_milestones(), Vec::from_array(&t.env, [1_000_000i128]));
+
+    // Dropping back under the milestone and crossing it again stays silent
+    t.client.withdraw_from_blend(&user, &token, &500_000);
+    t.client.supply_to_blend(&user, &token, &500_000, &None);
+    assert!(tvl_events(&t).is_empty());
+
+    // One deposit can cross a milestone added later and the next one up together
+    t.client.admin_add_tvl_milestone(&t.admin, &2_000_000);
+    t.client.supply_to_blend(&user, &token, &4_000_000, &None);
+    assert_eq!(tvl_events(&t), std::vec![
+        TvlEvent::TvlMilestoneReached(5_000_000, 5_200_000, ledger),
+        TvlEvent::TvlMilestoneReached(2_000_000, 5_200_000, ledger),
+    ]);
+    assert_eq!(t.client.get_crossed_tvl_milestones(), Vec::from_array(&t.env, [1_000_000i128, 5_000_000, 2_000_000]));
+}
+
+#[test]
+fn test_tvl_leaderboard_tracks_supplied_value() {
+    let t = setup();
+    let alice = Address::generate(&t.env);
+    let bob = Address::generate(&t.env);
+    let carol = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let other = create_token(&t.env);
+
+    assert_eq!(t.client.get_user_rank(&alice), None);
+    t.client.supply_to_blend(&alice, &token, &1_000, &None);
+    t.client.supply_to_blend(&bob, &token, &3_000, &None);
+    t.client.supply_to_blend(&carol, &token, &2_000, &None);
+    assert_eq!(t.client.get_tvl_leaderboard(&10), Vec::from_array(&t.env, [
+        (bob.clone(), 3_000i128),
+        (carol.clone(), 2_000),
+        (alice.clone(), 1_000),
+    ]));
+    assert_eq!(t.client.get_tvl_leaderboard(&1), Vec::from_array(&t.env, [(bob.clone(), 3_000i128)]));
+
+    // Supply across tokens counts towards one entry
+    t.client.supply_to_blend(&alice, &other, &2_500, &None);
+    assert_eq!(t.client.get_user_rank(&alice), Some(1));
+    assert_eq!(t.client.get_user_rank(&bob), Some(2));
+    assert_eq!(t.client.get_user_rank(&carol), Some(3));
+
+    t.client.withdraw_from_blend(&bob, &token, &1_500);
+    assert_eq!(t.client.get_user_rank(&carol), Some(2));
+    assert_eq!(t.client.get_user_rank(&bob), Some(3));
+
+    // Fully withdrawn users leave the leaderboard
+    t.client.withdraw_from_blend(&bob, &token, &1_500);
+    assert_eq!(t.client.get_user_rank(&bob), None);
+    assert_eq!(t.client.get_tvl_leaderboard(&10), Vec::from_array(&t.env, [
+        (alice.clone(), 3_500i128),
+        (carol.clone(), 2_000),
+    ]));
+}
+
+#[test]
+fn test_drawdown_protection_locks_positions() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.supply_to_blend(&user, &collateral, &1_000_000, &None);
+    assert!(t.client.try_enable_drawdown_protection(&user, &0).is_err());
+    t.client.enable_drawdown_protection(&user, &2000);
+    assert_eq!(t.client.get_drawdown_protection(&user), Some(DrawdownProtection {
+        enabled: true,
+        max_drawdown_pct_bps: 2000,
+        baseline_value_usd: 1_000_000,
+    }));
+
+    // A 20% drawdown is still within the limit
+    t.client.borrow_against_dust(&user, &debt, &100_000, &0);
+    t.client.withdraw_from_blend(&user, &collateral, &100_000);
+    assert_eq!(t.client.get_net_position_value(&user), 800_000);
+    assert_eq!(t.client.get_position_lock_status(&user, &collateral), None);
+
+    t.client.withdraw_from_blend(&user, &collateral, &50_000);
+    let topic = Symbol::new(&t.env, "DrawdownEvent");
+    let triggered = t.env.events().all().iter().any(|(_, topics, data)| {
+        topics.get(0).is_some_and(|first| Symbol::try_from_val(&t.env, &first) == Ok(topic.clone()))
+            && DrawdownEvent::try_from_val(&t.env, &data)
+                == Ok(DrawdownEvent::DrawdownProtectionTriggered(user.clone(), 1_000_000, 750_000, 2500))
+    });
+    assert!(triggered);
+    assert!(!t.client.get_drawdown_protection(&user).unwrap().enabled);
+    assert_eq!(t.client.get_position_lock_status(&user, &collateral), Some(u32::MAX));
+    assert_eq!(t.client.get_position_lock_status(&user, &debt), Some(u32::MAX));
+    assert!(t.client.try_withdraw_from_blend(&user, &collateral, &10_000).is_err());
+
+    t.client.disable_drawdown_protection(&user);
+    assert_eq!(t.client.get_drawdown_protection(&user), None);
+    assert_eq!(t.client.get_position_lock_status(&user, &collateral), None);
+    t.client.withdraw_from_blend(&user, &collateral, &10_000);
+}
+
+#[test]
+fn test_report_stale_oracle_pauses_token_and_pays_reporter() {
+    let t = setup();
+    let oracle = setup_oracle(&t);
+    let reporter = Address::generate(&t.env);
+    let funder = Address::generate(&t.env);
+    let user = Address::generate(&t.env);
+    let stablecoin = create_token(&t.env);
+    let token = create_token(&t.env);
+
+    t.client.admin_set_keeper_reward(&t.admin, &KeeperReward { token: stablecoin.clone(), amount: 25 });
+    mint(&t.env, &stablecoin, &funder, 40);
+    t.client.fund_keeper_reward_pool(&funder, &40);
+    t.env.ledger().with_mut(|li| li.timestamp = 10_000);
+    assert_eq!(t.client.get_blend_config().max_oracle_age, MAX_ORACLE_PRICE_AGE);
+
+    // An oracle exactly at the age limit is not stale
+    oracle.set_last_updated(&(10_000 - MAX_ORACLE_PRICE_AGE));
+    assert!(t.client.try_report_stale_oracle(&reporter, &token).is_err());
+
+    let last_updated = 10_000 - MAX_ORACLE_PRICE_AGE - 1;
+    oracle.set_last_updated(&last_updated);
+    assert_eq!(t.client.report_stale_oracle(&reporter, &token), 25);
+    let topic = Symbol::new(&t.env, "OracleEvent");
+    let paused = t.env.events().all().iter().any(|(_, topics, data)| {
+        topics.get(0).is_some_and(|first| Symbol::try_from_val(&t.env, &first) == Ok(topic.clone()))
+            && OracleEvent::try_from_val(&t.env, &data) == Ok(OracleEvent::StaleOraclePaused(token.clone(), last_updated))
+    });
+    assert!(paused);
+    assert_eq!(TokenClient::new(&t.env, &stablecoin).balance(&reporter), 25);
+    assert_eq!(t.client.get_keeper_reward_pool(), 15);
+    assert!(t.client.try_supply_to_blend(&user, &token, &1_000, &None).is_err());
+
+    // Repeat reports of a paused token are not paid again
+    assert!(t.client.try_report_stale_oracle(&reporter, &token).is_err());
+
+    assert!(t.client.try_admin_unpause_oracle_recovered(&t.admin, &token).is_err());
+    oracle.set_last_updated(&10_000);
+    t.client.admin_unpause_oracle_recovered(&t.admin, &token);
+    t.client.supply_to_blend(&user, &token, &1_000, &None);
+}
+
+#[test]
+fn test_operation_log_records_and_paginates() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let token = create_token(&t.env);
+    let debt = create_token(&t.env);
+    setup_router(&t, 10100);
+
+    t.client.supply_to_blend(&user, &token, &1_000_000, &None);
+    t.client.borrow_against_dust(&user, &debt, &200_000, &0);
+    t.client.repay_blend_debt(&user, &debt, &50_000);
+    t.client.withdraw_from_blend(&user, &token, &100_000);
+    t.client.flash_loan_arbitrage(&user, &arbitrage_params(&t.env, &token, 1_000_000));
+
+    let expected = [
+        ("supply", token.clone(), 1_000_000i128, t.pool.address.clone()),
+        ("borrow", debt.clone(), 200_000, t.pool.address.clone()),
+        ("repay", debt.clone(), 50_000, t.pool.address.clone()),
+        ("withdraw", token.clone(), 100_000, t.pool.address.clone()),
+        ("arb", token.clone(), 1_000_000, t.contract_id.clone()),
+    ];
+    let log = t.client.get_operation_log(&user, &0, &10);
+    assert_eq!(t.client.get_operation_count(&user), 5);
+    assert_eq!(log.len(), 5);
+    for (record, (op_type, token, amount, counterpart)) in log.iter().zip(expected.iter()) {
+        assert_eq!(record.op_type, Symbol::new(&t.env, op_type));
+        assert_eq!((&record.token, record.amount, &record.counterpart), (token, *amount, counterpart));
+        assert_eq!(record.ledger, t.env.ledger().sequence());
+    }
+
+    let page = t.client.get_operation_log(&user, &1, &2);
+    assert_eq!(page, log.slice(1..3));
+    assert_eq!(t.client.get_operation_log(&user, &4, &10), log.slice(4..5));
+    assert!(t.client.get_operation_log(&user, &5, &10).is_empty());
+    assert_eq!(t.client.get_operation_count(&Address::generate(&t.env)), 0);
+
+    // Past the cap the oldest records fall off
+    for _ in 0..OPERATION_LOG_CAPACITY {
+        t.client.supply_to_blend(&user, &token, &1, &None);
+    }
+    assert_eq!(t.client.get_operation_count(&user), OPERATION_LOG_CAPACITY);
+    let log = t.client.get_operation_log(&user, &0, &OPERATION_LOG_CAPACITY);
+    assert_eq!(log.get(0).unwrap().op_type, Symbol::new(&t.env, "supply"));
+    assert_eq!(log.get(0).unwrap().amount, 1);
+}
+
+#[test]
+fn test_transfer_borrow_position_moves_debt() {
+    let t = setup();
+    let from = Address::generate(&t.env);
+    let to = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    t.client.supply_to_blend(&from, &collateral, &1_000_000, &None);
+    t.client.supply_to_blend(&to, &collateral, &300_000, &None);
+    t.client.borrow_against_dust(&from, &debt, &300_000, &0);
+    let submitted_before = t.pool.submitted().len();
+
+    assert!(t.client.try_transfer_borrow_position(&from, &to, &debt, &300_001).is_err());
+    assert!(t.client.try_transfer_borrow_position(&from, &from, &debt, &100_000).is_err());
+
+    t.client.transfer_borrow_position(&from, &to, &debt, &120_000);
+    let topic = Symbol::new(&t.env, "PositionEvent");
+    let transferred = t.env.events().all().iter().any(|(_, topics, data)| {
+        topics.get(0).is_some_and(|first| Symbol::try_from_val(&t.env, &first) == Ok(topic.clone()))
+            && PositionEvent::try_from_val(&t.env, &data)
+                == Ok(PositionEvent::BorrowPositionTransferred(from.clone(), to.clone(), debt.clone(), 120_000))
+    });
+    assert!(transferred);
+
+    let from_balance = t.client.get_user_balance(&from, &debt);
+    assert_eq!(from_balance.borrowed_from_blend, 180_000);
+    assert_eq!(from_balance.balance, 300_000);
+    let to_balance = t.client.get_user_balance(&to, &debt);
+    assert_eq!(to_balance.borrowed_from_blend, 120_000);
+    assert_eq!(to_balance.balance, 0);
+
+    // The contract's Blend position is unchanged, so nothing is submitted to the pool
+    assert_eq!(t.pool.submitted().len(), submitted_before);
+
+    // The receiver cannot be pushed under the minimum health factor
+    assert!(t.client.try_transfer_borrow_position(&from, &to, &debt, &180_000).is_err());
+    assert_eq!(t.client.get_user_balance(&from, &debt).borrowed_from_blend, 180_000);
+}
+
+fn health_factor_alerts(t: &TestSetup) -> std::vec::Vec<HealthFactorEvent> {
+    let topic = Symbol::new(&t.env, "HealthFactorEvent");
+    let mut events = std::vec::Vec::new();
+    for (_, topics, data) in t.env.events().all().iter() {
+        if topics.get(0).is_some_and(|first| Symbol::try_from_val(&t.env, &first) == Ok(topic.clone())) {
+            events.push(HealthFactorEvent::try_from_val(&t.env, &data).unwrap());
+        }
     }
+    events
+}
+
+#[test]
+fn test_health_alerts_fire_on_edge_crossings_only() {
+    let t = setup();
+    let user = Address::generate(&t.env);
+    let collateral = create_token(&t.env);
+    let debt = create_token(&t.env);
+
+    let config = HealthFactorAlertConfig {
+        high_threshold: 2 * HEALTH_FACTOR_SCALE,
+        low_threshold: HEALTH_FACTOR_SCALE * 3 / 2,
+        last_emitted_above: true,
+    };
+    assert!(t.client.try_subscribe_health_alerts(&user, &HealthFactorAlertConfig {
+        high_threshold: config.low_threshold,
+        ..config.clone()
+    }).is_err());
+    t.client.subscribe_health_alerts(&user, &config);
+
+    // 1_000_000 collateral at 80%: 400_000 of debt is HF 2.0, 533_334 drops under 1.5
+    t.client.supply_to_blend(&user, &collateral, &1_000_000, &None);
+    t.client.borrow_against_dust(&user, &debt, &400_000, &0);
+    assert!(health_factor_alerts(&t).is_empty());
+
+    t.client.borrow_against_dust(&user, &debt, &133_334, &0);
+    let health_factor = 800_000 * HEALTH_FACTOR_SCALE / 533_334;
+    assert_eq!(health_factor_alerts(&t), std::vec![HealthFactorEvent::BelowLow(user.clone(), health_factor)]);
+    assert!(!t.client.get_health_alert_config(&user).unwrap().last_emitted_above);
+
+    // Staying below low, or recovering only into the band, stays quiet
+    t.client.borrow_against_dust(&user, &debt, &10_000, &0);
+    assert!(health_factor_alerts(&t).is_empty());
+    t.client.repay_blend_debt(&user, &debt, &143_334);
+    assert!(health_factor_alerts(&t).is_empty());
+
+    t.client.repay_blend_debt(&user, &debt, &1);
+    assert_eq!(health_factor_alerts(&t), std::vec![HealthFactorEvent::AboveHigh(user.clone(), 800_000 * HEALTH_FACTOR_SCALE / 399_999)]);
+    t.client.repay_blend_debt(&user, &debt, &100_000);
+    assert!(health_factor_alerts(&t).is_empty());
+
+    t.client.unsubscribe_health_alerts(&user);
+    t.client.borrow_against_dust(&user, &debt, &300_000, &0);
+    assert!(health_factor_alerts(&t).is_empty());
+    assert_eq!(t.client.get_health_alert_config(&user), None);
 }